@@ -0,0 +1,262 @@
+//! A circular list for shuffling elements around, as in day 20.
+#![allow(
+    clippy::cast_sign_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap
+)]
+
+/// A sequence of indices split into blocks of about `sqrt(n)` elements, so
+/// that finding, removing and inserting an index all take `O(sqrt(n))`.
+#[derive(Debug)]
+struct Blocks {
+    chunks: Vec<Vec<usize>>,
+    /// The block holding each index.
+    block_of: Vec<usize>,
+    /// The number of insertions since the blocks were last rebalanced.
+    inserts: usize,
+}
+
+impl Blocks {
+    /// Creates the sequence `0..len`.
+    fn new(len: usize) -> Self {
+        let mut blocks = Self {
+            chunks: vec![(0..len).collect()],
+            block_of: vec![0; len],
+            inserts: 0,
+        };
+        blocks.rebalance();
+        blocks
+    }
+
+    fn len(&self) -> usize {
+        self.block_of.len()
+    }
+
+    fn block_size(&self) -> usize {
+        self.len().isqrt().max(1)
+    }
+
+    /// Splits the sequence again into blocks of equal size.
+    fn rebalance(&mut self) {
+        let order = self.chunks.concat();
+        self.rebuild(&order);
+    }
+
+    /// Replaces the sequence with `order`, a permutation of the indices.
+    fn rebuild(&mut self, order: &[usize]) {
+        let size = self.block_size();
+        self.chunks = order.chunks(size).map(<[usize]>::to_vec).collect();
+        for (b, block) in self.chunks.iter().enumerate() {
+            for &i in block {
+                self.block_of[i] = b;
+            }
+        }
+        self.inserts = 0;
+    }
+
+    /// Returns the position of index `i` in the sequence.
+    fn position(&self, i: usize) -> usize {
+        let b = self.block_of[i];
+        let before = self.chunks[..b].iter().map(Vec::len).sum::<usize>();
+        before + self.chunks[b].iter().position(|&j| j == i).unwrap()
+    }
+
+    /// Returns the index at position `pos` in the sequence.
+    fn at(&self, mut pos: usize) -> usize {
+        for block in &self.chunks {
+            if pos < block.len() {
+                return block[pos];
+            }
+            pos -= block.len();
+        }
+        panic!("position out of bounds");
+    }
+
+    /// Removes index `i`, returning the position it had.
+    fn remove(&mut self, i: usize) -> usize {
+        let pos = self.position(i);
+        let block = &mut self.chunks[self.block_of[i]];
+        block.retain(|&j| j != i);
+        pos
+    }
+
+    /// Inserts index `i` so that it ends up at position `pos`.
+    fn insert(&mut self, mut pos: usize, i: usize) {
+        for (b, block) in self.chunks.iter_mut().enumerate() {
+            if pos <= block.len() {
+                block.insert(pos, i);
+                self.block_of[i] = b;
+                break;
+            }
+            pos -= block.len();
+        }
+
+        // blocks grow by at most one per insertion, so this keeps them under
+        // twice the block size
+        self.inserts += 1;
+        if self.inserts >= self.block_size() {
+            self.rebalance();
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.chunks.iter().flatten().copied()
+    }
+}
+
+/// A stable reference to an element of a [`CircularList`], valid however
+/// the element moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle(usize);
+
+/// A list whose ends are joined, with elements that can be moved around
+/// through their handles.
+///
+/// The list has a front so that it can be listed, but positions only matter
+/// relative to each other: an element moved to the front is put at the back
+/// instead.
+#[derive(Debug)]
+pub struct CircularList<T> {
+    values: Vec<T>,
+    order: Blocks,
+}
+
+impl<T> CircularList<T> {
+    /// Creates the list, the handles of the values being in the same order.
+    #[must_use]
+    pub fn from_vec(values: Vec<T>) -> Self {
+        let order = Blocks::new(values.len());
+        Self { values, order }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the handles in the order the values were given.
+    pub fn handles(&self) -> impl Iterator<Item = Handle> {
+        (0..self.len()).map(Handle)
+    }
+
+    #[must_use]
+    pub fn get(&self, handle: Handle) -> &T {
+        &self.values[handle.0]
+    }
+
+    /// Returns the handle of the first element matching the predicate,
+    /// starting from the front.
+    pub fn find(&self, mut predicate: impl FnMut(&T) -> bool) -> Option<Handle> {
+        self.order
+            .iter()
+            .find(|&i| predicate(&self.values[i]))
+            .map(Handle)
+    }
+
+    /// Returns the position of the element counting from the front.
+    #[must_use]
+    pub fn position(&self, handle: Handle) -> usize {
+        self.order.position(handle.0)
+    }
+
+    /// Moves the element `offset` places forward, or backward if negative,
+    /// past the other elements. Moving past all `n - 1` others brings the
+    /// element back where it was.
+    pub fn move_by(&mut self, handle: Handle, offset: isize) {
+        let len = self.len();
+        if len < 2 || offset % (len as isize - 1) == 0 {
+            return;
+        }
+        let pos = self.order.remove(handle.0);
+        let dst = (pos as isize + offset).rem_euclid(len as isize - 1) as usize;
+        // the front and the back are the same place in a circle, keep the
+        // element at the back
+        let dst = if dst == 0 { len - 1 } else { dst };
+        self.order.insert(dst, handle.0);
+    }
+
+    /// Moves the front `n` places forward, or backward if negative, leaving
+    /// the elements in the same circular order.
+    pub fn rotate(&mut self, n: isize) {
+        if self.is_empty() {
+            return;
+        }
+        let mut order = self.order.iter().collect::<Vec<_>>();
+        order.rotate_left(n.rem_euclid(self.len() as isize) as usize);
+        self.order.rebuild(&order);
+    }
+
+    /// Returns the handle `offset` places after the element, or before if
+    /// negative, going around the circle as often as needed.
+    #[must_use]
+    pub fn handle_from(&self, handle: Handle, offset: isize) -> Handle {
+        let len = self.len() as isize;
+        let pos = (self.position(handle) as isize + offset).rem_euclid(len);
+        Handle(self.order.at(pos as usize))
+    }
+
+    /// Returns the value `offset` places after the element, or before if
+    /// negative, going around the circle as often as needed.
+    #[must_use]
+    pub fn get_from(&self, handle: Handle, offset: isize) -> &T {
+        self.get(self.handle_from(handle, offset))
+    }
+
+    /// Iterates over the values from the front.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.order.iter().map(|i| &self.values[i])
+    }
+
+    /// Iterates once around the circle, starting from the element.
+    pub fn iter_from(&self, handle: Handle) -> impl Iterator<Item = &T> {
+        let pos = self.position(handle);
+        self.iter().skip(pos).chain(self.iter().take(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circular_list() {
+        let mut list = CircularList::from_vec(vec!['a', 'b', 'c', 'd', 'e']);
+        let handles = list.handles().collect::<Vec<_>>();
+        let [ha, hb, hc, hd, he] = handles[..] else {
+            unreachable!()
+        };
+        let listed = |list: &CircularList<char>| list.iter().collect::<String>();
+
+        list.move_by(hb, 2);
+        assert_eq!(listed(&list), "acdbe");
+        // moving past the four others comes back
+        list.move_by(hb, 4);
+        list.move_by(hb, -8);
+        assert_eq!(listed(&list), "acdbe");
+        // wrapping around the back
+        list.move_by(hd, 3);
+        assert_eq!(listed(&list), "adcbe");
+        list.move_by(ha, -1);
+        assert_eq!(listed(&list), "dcbae");
+        assert_eq!(list.position(ha), 3);
+
+        list.rotate(-1);
+        assert_eq!(listed(&list), "edcba");
+        list.rotate(7);
+        assert_eq!(listed(&list), "cbaed");
+        assert_eq!(list.iter_from(ha).collect::<String>(), "aedcb");
+        assert_eq!(list.iter_from(hc).collect::<String>(), "cbaed");
+
+        assert_eq!(*list.get_from(ha, 1), 'e');
+        assert_eq!(*list.get_from(ha, -1), 'b');
+        assert_eq!(*list.get_from(ha, 12), 'd');
+        assert_eq!(list.handle_from(he, -6), ha);
+        assert_eq!(list.find(|&v| v == 'd'), Some(hd));
+        assert_eq!(*list.get(he), 'e');
+    }
+}
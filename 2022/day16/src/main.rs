@@ -1,7 +1,6 @@
-use std::{collections::HashMap, io, str::FromStr};
+use std::{collections::HashMap, env, fmt::Write, io, iter, str::FromStr};
 
-use anyhow::Result;
-use itertools::iproduct;
+use anyhow::{anyhow, bail, ensure, Result};
 
 struct ParsedValve {
     name: String,
@@ -44,34 +43,84 @@ fn main() -> Result<()> {
         .map(|&s| s.parse::<ParsedValve>().unwrap())
         .collect::<Vec<_>>();
     let valves = to_valves(&parsed_valves);
-    let first_pos = parsed_valves.iter().position(|v| v.name == "AA").unwrap();
-    let positive_rates = valves.iter().filter(|v| v.rate > 0).count();
-    let total_rates = valves.iter().map(|v| v.rate).sum::<usize>();
-
-    let pressure = explore(
-        &valves,
-        &mut Vec::new(),
-        &mut Vec::new(),
-        first_pos,
-        0,
-        positive_rates,
-    );
-    println!("Part 1: {pressure}");
-
-    let pressure = double_explore(
-        &valves,
-        &mut Vec::new(),
-        &mut Vec::new(),
-        &mut Vec::new(),
-        first_pos,
-        first_pos,
-        0,
-        positive_rates,
-        total_rates,
-        0,
-        0,
-    );
-    println!("Part 2: {}", pressure.0);
+    let network = Network::new(&valves);
+
+    // Optional flags: [--plan] [--dot [--compressed] [--highlight]]
+    //                 [<agents> <minutes> [training] [start]]
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let mut flag = |name: &str| {
+        let found = args.iter().any(|arg| arg == name);
+        args.retain(|arg| arg != name);
+        found
+    };
+    let show_plan = flag("--plan");
+    let dot = flag("--dot");
+    let compressed = flag("--compressed");
+    let highlight = flag("--highlight");
+
+    let start = find_valve(&parsed_valves, "AA")?;
+    let mut scenarios = vec![
+        (
+            "Part 1".to_string(),
+            Scenario {
+                agents: 1,
+                minutes: 30,
+                training: 0,
+                start,
+            },
+        ),
+        (
+            "Part 2".to_string(),
+            Scenario {
+                agents: 2,
+                minutes: 30,
+                training: 4,
+                start,
+            },
+        ),
+    ];
+    if !args.is_empty() {
+        let scenario = Scenario {
+            agents: args[0].parse()?,
+            minutes: args
+                .get(1)
+                .ok_or_else(|| anyhow!("missing minutes"))?
+                .parse()?,
+            training: args.get(2).map_or(Ok(0), |s| s.parse())?,
+            start: find_valve(&parsed_valves, args.get(3).map_or("AA", String::as_str))?,
+        };
+        let label = format!(
+            "{} agent(s), {} minutes, {} training",
+            scenario.agents, scenario.minutes, scenario.training
+        );
+        scenarios.push((label, scenario));
+    }
+
+    if dot {
+        // Highlights the last scenario, i.e. the custom one if given.
+        let (_, scenario) = scenarios.last().unwrap();
+        let plan = highlight.then(|| network.best_plan(scenario));
+        let options = DotOptions {
+            compressed: compressed.then_some(scenario.start),
+            plan: plan.as_ref(),
+        };
+        print!("{}", to_dot(&parsed_valves, &network, &options));
+        return Ok(());
+    }
+
+    for (label, scenario) in &scenarios {
+        let plan = network.best_plan(scenario);
+        let replayed = plan.verify(&valves, scenario)?;
+        ensure!(
+            replayed == plan.pressure,
+            "{label}: plan releases {replayed}, not {}",
+            plan.pressure
+        );
+        println!("{label}: {}", plan.pressure);
+        if show_plan {
+            print!("{}", plan.describe(&parsed_valves));
+        }
+    }
 
     Ok(())
 }
@@ -91,267 +140,693 @@ fn to_valves(valves: &[ParsedValve]) -> Vec<Valve> {
         .collect()
 }
 
-fn explore(
-    valves: &[Valve],
-    open: &mut Vec<usize>,
-    actions: &mut Vec<(usize, bool)>,
-    cur: usize,
-    mut minute: usize,
-    positive_rates: usize,
-) -> usize {
-    minute += 1;
-    if minute >= 30 {
-        return 0;
-    }
-    if open.len() == positive_rates {
-        return 0;
+/// Returns the index of the valve with the given name.
+fn find_valve(valves: &[ParsedValve], name: &str) -> Result<usize> {
+    valves
+        .iter()
+        .position(|v| v.name == name)
+        .ok_or_else(|| anyhow!("no valve named {name}"))
+}
+
+/// The parameters of a single exploration.
+#[derive(Debug, Clone, Copy)]
+struct Scenario {
+    /// The number of agents opening valves in parallel.
+    agents: usize,
+    /// The total number of minutes before the volcano erupts.
+    minutes: usize,
+    /// The minutes spent before any agent starts moving.
+    training: usize,
+    /// The valve every agent starts from.
+    start: usize,
+}
+
+impl Scenario {
+    /// Returns the minutes each agent has for moving and opening valves.
+    fn time(&self) -> usize {
+        self.minutes.saturating_sub(self.training)
     }
+}
 
-    let cur_valve = &valves[cur];
+/// The tunnel network reduced to the valves worth opening.
+struct Network<'a> {
+    valves: &'a [Valve],
+    /// Indices of the valves with a positive flow rate.
+    targets: Vec<usize>,
+    /// `dist[a][b]` is the number of minutes to walk from valve `a` to `b`,
+    /// or `None` if no tunnels lead there.
+    dist: Vec<Vec<Option<usize>>>,
+}
 
-    let mut max_pressure = 0;
-    let mut max_unopened = 0;
-    for (count, &(id, opened)) in actions.iter().rev().enumerate() {
-        if opened {
-            if count > 0 && id == cur {
-                return 0;
-            }
-            break;
+impl<'a> Network<'a> {
+    fn new(valves: &'a [Valve]) -> Self {
+        let targets = (0..valves.len())
+            .filter(|&i| valves[i].rate > 0)
+            .collect::<Vec<_>>();
+        let dist = (0..valves.len())
+            .map(|from| distances(valves, from))
+            .collect::<Vec<_>>();
+        Network {
+            valves,
+            targets,
+            dist,
         }
-        if id == cur {
-            return 0;
+    }
+
+    /// Returns a plan releasing the most pressure for `scenario`.
+    fn best_plan(&self, scenario: &Scenario) -> Plan {
+        let time = scenario.time();
+        let (single, totals) = self.totals(scenario);
+
+        // Walk back through the layers to find the set each agent opens.
+        let mut set = single.len() - 1;
+        let mut sets = Vec::new();
+        for k in (1..totals.len()).rev() {
+            let sub = submasks(set)
+                .find(|&sub| single[sub] + totals[k - 1][set ^ sub] == totals[k][set])
+                .unwrap();
+            sets.push(sub);
+            set ^= sub;
         }
-        if !open.contains(&id) && valves[id].rate > max_unopened {
-            max_unopened = valves[id].rate;
+        if !totals.is_empty() {
+            sets.push(set);
+        }
+
+        let agents = sets
+            .into_iter()
+            .rev()
+            .map(|set| {
+                let (_, order) = self.best_order(scenario.start, time, set);
+                let mut actions = Vec::new();
+                let mut cur = scenario.start;
+                for target in order {
+                    let path = shortest_path(self.valves, cur, target)
+                        .expect("plans only open valves in reach");
+                    actions.extend(path.into_iter().map(Action::Move));
+                    actions.push(Action::Open(target));
+                    cur = target;
+                }
+                AgentPlan {
+                    start: scenario.start,
+                    first_minute: scenario.training + 1,
+                    actions,
+                }
+            })
+            .collect();
+
+        Plan {
+            pressure: totals.last().map_or(0, |total| total[total.len() - 1]),
+            agents,
         }
     }
 
-    if cur_valve.rate > max_unopened && !open.contains(&cur) {
-        // move after opening the valve
-        open.push(cur);
-        actions.push((cur, true));
+    /// Returns the best pressure for every set of targets opened by a single
+    /// agent, and for each number of agents from one to `scenario.agents`.
+    fn totals(&self, scenario: &Scenario) -> (Vec<usize>, Vec<Vec<usize>>) {
+        let single = self.best_by_set(scenario.start, scenario.time());
+        let mut totals = Vec::new();
+        if scenario.agents == 0 {
+            return (single, totals);
+        }
+
+        // Each agent opens a disjoint set of valves, so the best total for a
+        // set is the best split of it between one more agent and the rest.
+        totals.push(single.clone());
+        for _ in 1..scenario.agents {
+            let prev = totals.last().unwrap();
+            let total = (0..single.len())
+                .map(|set| {
+                    submasks(set)
+                        .map(|sub| single[sub] + prev[set ^ sub])
+                        .max()
+                        .unwrap_or_default()
+                })
+                .collect();
+            totals.push(total);
+        }
+        (single, totals)
+    }
 
-        let pressure = explore(valves, open, actions, cur, minute, positive_rates);
-        max_pressure = pressure + cur_valve.rate * (30 - minute);
+    /// Returns, for every set of targets, the most pressure a single agent
+    /// can release by opening at most those valves within `time` minutes.
+    fn best_by_set(&self, start: usize, time: usize) -> Vec<usize> {
+        let mut best = vec![0; 1 << self.targets.len()];
+        self.visit(start, time, 0, 0, &mut best);
 
-        actions.pop();
-        open.pop();
+        for bit in 0..self.targets.len() {
+            for set in 0..best.len() {
+                if set & 1 << bit != 0 {
+                    best[set] = best[set].max(best[set ^ 1 << bit]);
+                }
+            }
+        }
+        best
     }
 
-    // move without opening the valve
-    actions.push((cur, false));
+    fn visit(&self, cur: usize, time: usize, opened: usize, pressure: usize, best: &mut [usize]) {
+        best[opened] = best[opened].max(pressure);
 
-    for &next in &cur_valve.next {
-        let pressure = explore(valves, open, actions, next, minute, positive_rates);
-        if pressure > max_pressure {
-            max_pressure = pressure;
+        for (i, &target) in self.targets.iter().enumerate() {
+            if opened & 1 << i != 0 {
+                continue;
+            }
+            // walk to the valve and open it
+            let Some(dist) = self.dist[cur][target] else {
+                continue;
+            };
+            let cost = dist + 1;
+            if cost >= time {
+                continue;
+            }
+            let remaining = time - cost;
+            self.visit(
+                target,
+                remaining,
+                opened | 1 << i,
+                pressure + self.valves[target].rate * remaining,
+                best,
+            );
         }
     }
 
-    actions.pop();
-    max_pressure
+    /// Returns the most pressure a single agent can release by opening only
+    /// valves in `allowed`, along with the valves in the order to open them.
+    fn best_order(&self, cur: usize, time: usize, allowed: usize) -> (usize, Vec<usize>) {
+        let mut best = (0, Vec::new());
+        for (i, &target) in self.targets.iter().enumerate() {
+            if allowed & 1 << i == 0 {
+                continue;
+            }
+            let Some(dist) = self.dist[cur][target] else {
+                continue;
+            };
+            let cost = dist + 1;
+            if cost >= time {
+                continue;
+            }
+            let remaining = time - cost;
+            let (pressure, mut order) = self.best_order(target, remaining, allowed & !(1 << i));
+            let pressure = pressure + self.valves[target].rate * remaining;
+            if pressure > best.0 {
+                order.insert(0, target);
+                best = (pressure, order);
+            }
+        }
+        best
+    }
 }
 
-#[allow(clippy::too_many_lines, clippy::too_many_arguments)]
-fn double_explore(
-    valves: &[Valve],
-    open: &mut Vec<usize>,
-    actions1: &mut Vec<(usize, bool)>,
-    actions2: &mut Vec<(usize, bool)>,
-    cur1: usize,
-    cur2: usize,
-    mut minute: usize,
-    positive_rates: usize,
-    remaining_rates: usize,
-    cur_pressure: usize,
-    mut max_pressure_seen: usize,
-) -> (usize, usize) {
-    minute += 1;
-    if minute >= 26 {
-        return (0, max_pressure_seen);
-    }
-    if open.len() == positive_rates {
-        return (0, max_pressure_seen);
+/// What an agent does during a single minute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// Walks through a tunnel to the given valve.
+    Move(usize),
+    /// Opens the valve the agent stands at.
+    Open(usize),
+}
+
+/// The actions of a single agent, one per minute.
+#[derive(Debug)]
+struct AgentPlan {
+    /// The valve the agent starts from.
+    start: usize,
+    /// The minute during which the first action is taken.
+    first_minute: usize,
+    actions: Vec<Action>,
+}
+
+impl AgentPlan {
+    /// Iterates over the opened valves along with the minute each is opened.
+    fn openings(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.actions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, action)| match action {
+                Action::Open(valve) => Some((self.first_minute + i, *valve)),
+                Action::Move(_) => None,
+            })
     }
-    if cur_pressure + remaining_rates * (26 - minute) < max_pressure_seen {
-        return (0, max_pressure_seen);
+
+    /// Returns the valves the agent walks through, starting from `start`.
+    fn route(&self) -> Vec<usize> {
+        let mut route = vec![self.start];
+        route.extend(self.actions.iter().filter_map(|action| match action {
+            Action::Move(valve) => Some(*valve),
+            Action::Open(_) => None,
+        }));
+        route
     }
+}
 
-    let cur_valve1 = &valves[cur1];
-    let cur_valve2 = &valves[cur2];
+/// The actions of every agent and the pressure they release together.
+#[derive(Debug)]
+struct Plan {
+    pressure: usize,
+    agents: Vec<AgentPlan>,
+}
 
-    let mut max_pressure = 0;
-    let mut max_unopened1 = 0;
-    for (count, &(id, opened)) in actions1.iter().rev().enumerate() {
-        if opened {
-            if count > 0 && id == cur1 {
-                return (0, max_pressure_seen);
-            }
-            break;
-        }
-        if id == cur1 {
-            return (0, max_pressure_seen);
+impl Plan {
+    /// Replays the plan minute by minute and returns the pressure released.
+    fn verify(&self, valves: &[Valve], scenario: &Scenario) -> Result<usize> {
+        ensure!(
+            self.agents.len() <= scenario.agents,
+            "{} agents planned, only {} available",
+            self.agents.len(),
+            scenario.agents
+        );
+        let mut pos = Vec::new();
+        for (n, agent) in self.agents.iter().enumerate() {
+            ensure!(agent.start == scenario.start, "agent {n} starts elsewhere");
+            ensure!(
+                agent.first_minute > scenario.training,
+                "agent {n} acts during training"
+            );
+            // an agent still training when time runs out has nothing to do
+            ensure!(
+                agent.actions.is_empty()
+                    || agent.first_minute + agent.actions.len() <= scenario.minutes + 1,
+                "agent {n} acts after minute {}",
+                scenario.minutes
+            );
+            pos.push(agent.start);
         }
-        if !open.contains(&id) && valves[id].rate > max_unopened1 {
-            max_unopened1 = valves[id].rate;
+
+        let mut open = vec![false; valves.len()];
+        let mut flow = 0;
+        let mut pressure = 0;
+        for minute in 1..=scenario.minutes {
+            pressure += flow;
+            for (n, agent) in self.agents.iter().enumerate() {
+                let Some(action) = minute
+                    .checked_sub(agent.first_minute)
+                    .and_then(|i| agent.actions.get(i))
+                else {
+                    continue;
+                };
+                match *action {
+                    Action::Move(next) => {
+                        if !valves[pos[n]].next.contains(&next) {
+                            bail!("minute {minute}: agent {n} has no tunnel to valve {next}");
+                        }
+                        pos[n] = next;
+                    }
+                    Action::Open(valve) => {
+                        if pos[n] != valve {
+                            bail!("minute {minute}: agent {n} is not at valve {valve}");
+                        }
+                        if open[valve] {
+                            bail!("minute {minute}: valve {valve} is already open");
+                        }
+                        open[valve] = true;
+                    }
+                }
+            }
+            flow = (0..valves.len())
+                .filter(|&i| open[i])
+                .map(|i| valves[i].rate)
+                .sum();
         }
+        Ok(pressure)
     }
-    let mut max_unopened2 = 0;
-    let mut max_record = Vec::new();
-    for (count, &(id, opened)) in actions2.iter().rev().enumerate() {
-        if opened {
-            if count > 0 && id == cur2 {
-                return (0, max_pressure_seen);
+
+    /// Describes the plan using the valve names.
+    fn describe(&self, valves: &[ParsedValve]) -> String {
+        let mut s = String::new();
+        for (n, agent) in self.agents.iter().enumerate() {
+            let route = agent
+                .route()
+                .iter()
+                .map(|&i| valves[i].name.as_str())
+                .collect::<Vec<_>>();
+            writeln!(s, "Agent {}: {}", n + 1, route.join(" -> ")).unwrap();
+            for (minute, valve) in agent.openings() {
+                writeln!(
+                    s,
+                    "  minute {minute:2}: open valve {} (rate {})",
+                    valves[valve].name, valves[valve].rate
+                )
+                .unwrap();
             }
-            break;
-        }
-        if id == cur2 {
-            return (0, max_pressure_seen);
-        }
-        if !open.contains(&id) && valves[id].rate > max_unopened2 {
-            max_unopened2 = valves[id].rate;
         }
+        s
     }
+}
 
-    let can_open1 = cur_valve1.rate > max_unopened1 && !open.contains(&cur1);
-    let can_open2 = cur_valve2.rate > max_unopened2 && !open.contains(&cur2);
+/// Options for [`to_dot`].
+#[derive(Default)]
+struct DotOptions<'a> {
+    /// Draws only the given start valve and the valves worth opening, joined
+    /// by edges weighted with the walking distance between them.
+    compressed: Option<usize>,
+    /// Highlights the route of each agent and the valves it opens.
+    plan: Option<&'a Plan>,
+}
 
-    if can_open1 && can_open2 && cur1 != cur2 {
-        // both opens
-        open.push(cur1);
-        open.push(cur2);
-        actions1.push((cur1, true));
-        actions2.push((cur2, true));
+const AGENT_COLORS: [&str; 5] = ["red", "blue", "darkgreen", "orange", "purple"];
 
-        let additional_pressure = cur_valve1.rate * (26 - minute) + cur_valve2.rate * (26 - minute);
-        max_pressure_seen = max_pressure_seen.max(cur_pressure + additional_pressure);
-        let (pressure, new_max_pressure_seen) = double_explore(
-            valves,
-            open,
-            actions1,
-            actions2,
-            cur1,
-            cur2,
-            minute,
-            positive_rates,
-            remaining_rates - cur_valve1.rate - cur_valve2.rate,
-            cur_pressure + additional_pressure,
-            max_pressure_seen,
-        );
-        max_pressure = pressure + additional_pressure;
-        max_pressure_seen = new_max_pressure_seen;
-        max_record.push((minute, cur1));
-        max_record.push((minute, cur2));
-
-        actions1.pop();
-        actions2.pop();
-        open.pop();
-        open.pop();
-    }
+/// Exports the tunnel network as a Graphviz DOT graph.
+fn to_dot(valves: &[ParsedValve], network: &Network, options: &DotOptions) -> String {
+    let n = valves.len();
 
-    if can_open1 {
-        // first opens and second moves
-        open.push(cur1);
-        actions1.push((cur1, true));
-        actions2.push((cur2, false));
-
-        let mut sibling_max_pressure = 0;
-        let additional_pressure = cur_valve1.rate * (26 - minute);
-        for &next in &cur_valve2.next {
-            max_pressure_seen = max_pressure_seen.max(cur_pressure + additional_pressure);
-            let (pressure, new_max_pressure_seen) = double_explore(
-                valves,
-                open,
-                actions1,
-                actions2,
-                cur1,
-                next,
-                minute,
-                positive_rates,
-                remaining_rates - cur_valve1.rate,
-                cur_pressure + additional_pressure,
-                max_pressure_seen,
-            );
-            if pressure > sibling_max_pressure {
-                sibling_max_pressure = pressure;
-                max_pressure_seen = new_max_pressure_seen;
+    // edges[a][b] for a < b, holding the colors of the agents walking it
+    let mut edges = vec![vec![None::<Vec<&str>>; n]; n];
+    let mut opened = vec![None; n];
+    match options.compressed {
+        Some(start) => {
+            let mut nodes = network.targets.clone();
+            if !nodes.contains(&start) {
+                nodes.insert(0, start);
+            }
+            for (i, &a) in nodes.iter().enumerate() {
+                for &b in &nodes[i + 1..] {
+                    if network.dist[a][b].is_some() {
+                        edges[a.min(b)][a.max(b)] = Some(Vec::new());
+                    }
+                }
+            }
+        }
+        None => {
+            for (a, valve) in network.valves.iter().enumerate() {
+                for &b in &valve.next {
+                    edges[a.min(b)][a.max(b)] = Some(Vec::new());
+                }
+            }
+        }
+    }
+    for (agent, color) in options
+        .plan
+        .iter()
+        .flat_map(|plan| &plan.agents)
+        .zip(AGENT_COLORS.iter().cycle())
+    {
+        // the compressed graph jumps straight between opened valves
+        let route = if options.compressed.is_some() {
+            iter::once(agent.start)
+                .chain(agent.openings().map(|(_, valve)| valve))
+                .collect()
+        } else {
+            agent.route()
+        };
+        for pair in route.windows(2) {
+            let (a, b) = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+            if let Some(colors) = &mut edges[a][b] {
+                if !colors.contains(color) {
+                    colors.push(color);
+                }
             }
         }
-        sibling_max_pressure += additional_pressure;
-        if sibling_max_pressure > max_pressure {
-            max_pressure = sibling_max_pressure;
-            max_record.push((minute, cur1));
+        for (_, valve) in agent.openings() {
+            opened[valve] = Some(*color);
         }
+    }
 
-        actions1.pop();
-        actions2.pop();
-        open.pop();
+    let mut s = String::from("graph valves {\n");
+    for (i, valve) in valves.iter().enumerate() {
+        if options
+            .compressed
+            .is_some_and(|start| i != start && valve.rate == 0)
+        {
+            continue;
+        }
+        let shape = if Some(i) == options.compressed {
+            "doublecircle"
+        } else {
+            "circle"
+        };
+        let mut attrs = format!("label=\"{}\\n{}\", shape={shape}", valve.name, valve.rate);
+        if let Some(color) = opened[i] {
+            write!(attrs, ", style=filled, fillcolor={color}, fontcolor=white").unwrap();
+        } else if valve.rate == 0 {
+            attrs.push_str(", style=dashed, color=gray, fontcolor=gray");
+        }
+        writeln!(s, "    {} [{attrs}];", valve.name).unwrap();
+    }
+    for (a, row) in edges.iter().enumerate() {
+        for (b, edge) in row.iter().enumerate() {
+            let Some(colors) = edge else {
+                continue;
+            };
+            let mut attrs = Vec::new();
+            if options.compressed.is_some() {
+                attrs.push(format!("label={}", network.dist[a][b].unwrap()));
+            }
+            if !colors.is_empty() {
+                attrs.push(format!("color=\"{}\", penwidth=2", colors.join(":")));
+            }
+            write!(s, "    {} -- {}", valves[a].name, valves[b].name).unwrap();
+            if !attrs.is_empty() {
+                write!(s, " [{}]", attrs.join(", ")).unwrap();
+            }
+            s.push_str(";\n");
+        }
     }
+    s.push_str("}\n");
+    s
+}
 
-    if can_open2 {
-        // first moves and second opens
-        open.push(cur2);
-        actions1.push((cur1, false));
-        actions2.push((cur2, true));
-
-        let mut sibling_max_pressure = 0;
-        let additional_pressure = cur_valve2.rate * (26 - minute);
-        for &next in &cur_valve1.next {
-            max_pressure_seen = max_pressure_seen.max(cur_pressure + additional_pressure);
-            let (pressure, new_max_pressure_seen) = double_explore(
-                valves,
-                open,
-                actions1,
-                actions2,
-                next,
-                cur2,
-                minute,
-                positive_rates,
-                remaining_rates - cur_valve2.rate,
-                cur_pressure + additional_pressure,
-                max_pressure_seen,
-            );
-            if pressure > sibling_max_pressure {
-                sibling_max_pressure = pressure;
-                max_pressure_seen = new_max_pressure_seen;
+/// Returns the valves along a shortest walk from `from` to `to`, excluding
+/// `from`, or `None` if no tunnels lead there.
+fn shortest_path(valves: &[Valve], from: usize, to: usize) -> Option<Vec<usize>> {
+    let mut prev = vec![usize::MAX; valves.len()];
+    prev[from] = from;
+    let mut queue = vec![from];
+    let mut i = 0;
+    while i < queue.len() && prev[to] == usize::MAX {
+        let cur = queue[i];
+        i += 1;
+        for &next in &valves[cur].next {
+            if prev[next] == usize::MAX {
+                prev[next] = cur;
+                queue.push(next);
             }
         }
-        sibling_max_pressure += additional_pressure;
-        if sibling_max_pressure > max_pressure {
-            max_pressure = sibling_max_pressure;
-            max_record.push((minute, cur2));
+    }
+
+    if prev[to] == usize::MAX {
+        return None;
+    }
+    let mut path = Vec::new();
+    let mut cur = to;
+    while cur != from {
+        path.push(cur);
+        cur = prev[cur];
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Returns the walking distances from `from` to every valve, `None` for the
+/// valves out of reach.
+fn distances(valves: &[Valve], from: usize) -> Vec<Option<usize>> {
+    let mut dist = vec![None; valves.len()];
+    dist[from] = Some(0);
+    let mut queue = vec![from];
+    let mut i = 0;
+    while i < queue.len() {
+        let cur = queue[i];
+        i += 1;
+        for &next in &valves[cur].next {
+            if dist[next].is_none() {
+                dist[next] = dist[cur].map(|d| d + 1);
+                queue.push(next);
+            }
         }
+    }
+    dist
+}
+
+/// Iterates over every subset of `set`, including `set` itself and the empty
+/// set.
+fn submasks(set: usize) -> impl Iterator<Item = usize> {
+    let mut next = Some(set);
+    std::iter::from_fn(move || {
+        let cur = next?;
+        next = if cur == 0 {
+            None
+        } else {
+            Some((cur - 1) & set)
+        };
+        Some(cur)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = include_str!("../input/test.txt");
 
-        actions1.pop();
-        actions2.pop();
-        open.pop();
+    fn parse(input: &str) -> (Vec<ParsedValve>, Vec<Valve>) {
+        let parsed = input
+            .lines()
+            .map(|s| s.parse::<ParsedValve>().unwrap())
+            .collect::<Vec<_>>();
+        let valves = to_valves(&parsed);
+        (parsed, valves)
     }
 
-    // both move without opening any valve
-    actions1.push((cur1, false));
-    actions2.push((cur2, false));
+    #[test]
+    fn example() {
+        let (parsed, valves) = parse(INPUT);
+        let network = Network::new(&valves);
+        let start = find_valve(&parsed, "AA").unwrap();
+
+        let scenario = |agents, training| Scenario {
+            agents,
+            minutes: 30,
+            training,
+            start,
+        };
+        assert_eq!(network.best_plan(&scenario(1, 0)).pressure, 1651);
+        assert_eq!(network.best_plan(&scenario(2, 4)).pressure, 1707);
+        assert_eq!(network.best_plan(&scenario(0, 0)).pressure, 0);
+    }
 
-    for (&next1, &next2) in iproduct!(cur_valve1.next.iter(), cur_valve2.next.iter()) {
-        let (pressure, new_max_pressure_seen) = double_explore(
-            valves,
-            open,
-            actions1,
-            actions2,
-            next1,
-            next2,
-            minute,
-            positive_rates,
-            remaining_rates,
-            cur_pressure,
-            max_pressure_seen,
+    #[test]
+    fn plan() {
+        let (parsed, valves) = parse(INPUT);
+        let network = Network::new(&valves);
+        let start = find_valve(&parsed, "AA").unwrap();
+
+        for (agents, training) in [(1, 0), (2, 4), (3, 10)] {
+            let scenario = Scenario {
+                agents,
+                minutes: 30,
+                training,
+                start,
+            };
+            let plan = network.best_plan(&scenario);
+            assert_eq!(plan.verify(&valves, &scenario).unwrap(), plan.pressure);
+        }
+
+        // training longer than the time available
+        let scenario = Scenario {
+            agents: 1,
+            minutes: 5,
+            training: 10,
+            start,
+        };
+        let plan = network.best_plan(&scenario);
+        assert_eq!(plan.pressure, 0);
+        assert_eq!(plan.verify(&valves, &scenario).unwrap(), 0);
+
+        let scenario = Scenario {
+            agents: 1,
+            minutes: 30,
+            training: 0,
+            start,
+        };
+        let plan = network.best_plan(&scenario);
+        let opened = plan.agents[0]
+            .openings()
+            .map(|(minute, valve)| (minute, parsed[valve].name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            opened,
+            [
+                (2, "DD"),
+                (5, "BB"),
+                (9, "JJ"),
+                (17, "HH"),
+                (21, "EE"),
+                (24, "CC")
+            ]
         );
-        max_pressure_seen = new_max_pressure_seen;
-        if pressure > max_pressure {
-            max_pressure = pressure;
+    }
+
+    #[test]
+    fn disconnected() {
+        let (parsed, valves) = parse(
+            "Valve AA has flow rate=0; tunnel leads to valve BB\n\
+             Valve BB has flow rate=5; tunnel leads to valve AA\n\
+             Valve CC has flow rate=9; tunnel leads to valve DD\n\
+             Valve DD has flow rate=0; tunnel leads to valve CC",
+        );
+        let network = Network::new(&valves);
+        let start = find_valve(&parsed, "AA").unwrap();
+
+        for (agents, training, pressure) in [(1, 0, 140), (2, 4, 120)] {
+            let scenario = Scenario {
+                agents,
+                minutes: 30,
+                training,
+                start,
+            };
+            let plan = network.best_plan(&scenario);
+            assert_eq!(plan.pressure, pressure);
+            assert_eq!(plan.verify(&valves, &scenario).unwrap(), pressure);
         }
+
+        let cc = find_valve(&parsed, "CC").unwrap();
+        let dd = find_valve(&parsed, "DD").unwrap();
+        assert_eq!(shortest_path(&valves, start, cc), None);
+        assert_eq!(shortest_path(&valves, dd, cc), Some(vec![cc]));
+        assert_eq!(shortest_path(&valves, cc, cc), Some(vec![]));
+
+        let graph = to_dot(
+            &parsed,
+            &network,
+            &DotOptions {
+                compressed: Some(start),
+                plan: None,
+            },
+        );
+        assert!(graph.contains("    AA -- BB [label=1];\n"));
+        assert_eq!(graph.matches(" -- ").count(), 1);
     }
 
-    actions1.pop();
-    actions2.pop();
-    (max_pressure, max_pressure_seen)
+    #[test]
+    fn verify_rejects_invalid_plan() {
+        let (parsed, valves) = parse(INPUT);
+        let start = find_valve(&parsed, "AA").unwrap();
+        let scenario = Scenario {
+            agents: 1,
+            minutes: 30,
+            training: 0,
+            start,
+        };
+        let hh = find_valve(&parsed, "HH").unwrap();
+        let plan = Plan {
+            pressure: 0,
+            agents: vec![AgentPlan {
+                start,
+                first_minute: 1,
+                actions: vec![Action::Move(hh), Action::Open(hh)],
+            }],
+        };
+        assert!(plan.verify(&valves, &scenario).is_err());
+    }
+
+    #[test]
+    fn dot() {
+        let (parsed, valves) = parse(INPUT);
+        let network = Network::new(&valves);
+        let start = find_valve(&parsed, "AA").unwrap();
+
+        let graph = to_dot(&parsed, &network, &DotOptions::default());
+        assert!(graph.starts_with("graph valves {\n"));
+        assert!(graph.contains("    BB [label=\"BB\\n13\", shape=circle];\n"));
+        assert!(graph.contains("    AA -- BB;\n"));
+        assert_eq!(graph.matches(" -- ").count(), 10);
+
+        let plan = network.best_plan(&Scenario {
+            agents: 1,
+            minutes: 30,
+            training: 0,
+            start,
+        });
+        let graph = to_dot(
+            &parsed,
+            &network,
+            &DotOptions {
+                compressed: Some(start),
+                plan: Some(&plan),
+            },
+        );
+        assert!(!graph.contains("FF"));
+        assert!(graph.contains("    AA -- DD [label=1, color=\"red\", penwidth=2];\n"));
+        assert!(graph.contains("    BB -- JJ [label=3, color=\"red\", penwidth=2];\n"));
+        assert!(graph.contains("    AA -- HH [label=5];\n"));
+        assert_eq!(graph.matches(" -- ").count(), 21);
+    }
 }
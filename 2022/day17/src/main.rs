@@ -1,61 +1,135 @@
-use std::io;
-
-use anyhow::Result;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum RockKind {
-    A, // -
-    B, // +
-    C, // _|
-    D, // |
-    E, // []
+use std::{collections::HashMap, env, fs, io, str::FromStr};
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+/// A row of the chamber or of a rock, one bit per column with the leftmost
+/// column as the highest bit.
+type Row = u64;
+
+/// The rocks from the puzzle, in the order they fall.
+const DEFAULT_SHAPES: &str = "\
+####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##
+";
+
+/// A rock shape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Shape {
+    /// The rows of the shape from bottom to top, with the rightmost column of
+    /// the shape as bit 0.
+    rows: Vec<Row>,
+    width: usize,
 }
 
-impl RockKind {
-    #[allow(clippy::match_same_arms)]
-    fn height(self) -> usize {
-        match self {
-            RockKind::A => 1,
-            RockKind::B => 3,
-            RockKind::C => 3,
-            RockKind::D => 4,
-            RockKind::E => 2,
+impl FromStr for Shape {
+    type Err = anyhow::Error;
+
+    /// Parses a shape drawn with `#` for rock and `.` for air, top row first.
+    fn from_str(s: &str) -> Result<Self> {
+        let lines = s.lines().map(str::trim_end).collect::<Vec<_>>();
+        let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        ensure!(width > 0, "empty rock shape");
+        ensure!(
+            width <= Row::BITS as usize,
+            "rock shape wider than {} columns",
+            Row::BITS
+        );
+
+        let mut rows = Vec::new();
+        for line in lines.iter().rev() {
+            let mut row = 0;
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    '#' => row |= 1 << (width - 1 - x),
+                    '.' => {}
+                    _ => bail!("invalid character {c:?} in rock shape"),
+                }
+            }
+            rows.push(row);
         }
+        ensure!(
+            rows.first() != Some(&0) && rows.last() != Some(&0),
+            "rock shape has empty rows"
+        );
+        Ok(Self { rows, width })
     }
+}
 
-    #[allow(clippy::match_same_arms)]
-    fn width(self) -> usize {
-        match self {
-            RockKind::A => 4,
-            RockKind::B => 3,
-            RockKind::C => 3,
-            RockKind::D => 1,
-            RockKind::E => 2,
+/// Parses shapes separated by blank lines.
+fn parse_shapes(s: &str) -> Result<Vec<Shape>> {
+    let shapes = s
+        .split("\n\n")
+        .filter(|block| !block.trim().is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<Shape>>>()?;
+    ensure!(!shapes.is_empty(), "no rock shapes");
+    Ok(shapes)
+}
+
+/// The chamber and how rocks appear in it.
+#[derive(Debug, Clone)]
+struct Config {
+    shapes: Vec<Shape>,
+    width: usize,
+    /// The gap between the left wall and the left edge of a new rock.
+    spawn_x: usize,
+    /// The gap between the top of the tower and the bottom edge of a new rock.
+    spawn_y: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shapes: parse_shapes(DEFAULT_SHAPES).unwrap(),
+            width: 7,
+            spawn_x: 2,
+            spawn_y: 3,
         }
     }
+}
 
-    fn iter() -> impl Iterator<Item = RockKind> {
-        vec![
-            RockKind::A,
-            RockKind::B,
-            RockKind::C,
-            RockKind::D,
-            RockKind::E,
-        ]
-        .into_iter()
-        .cycle()
+impl Config {
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.width <= Row::BITS as usize,
+            "chamber wider than {} columns",
+            Row::BITS
+        );
+        for shape in &self.shapes {
+            ensure!(
+                self.spawn_x + shape.width <= self.width,
+                "a rock of width {} does not fit in the chamber",
+                shape.width
+            );
+        }
+        Ok(())
     }
 }
 
 #[derive(Clone, Debug)]
-struct Rock {
-    kind: RockKind,
+struct Rock<'a> {
+    shape: &'a Shape,
     coord: (usize, usize),
 }
 
-impl Rock {
-    fn new(kind: RockKind, coord: (usize, usize)) -> Self {
-        Self { kind, coord }
+impl<'a> Rock<'a> {
+    fn new(shape: &'a Shape, coord: (usize, usize)) -> Self {
+        Self { shape, coord }
     }
 
     fn x(&self) -> usize {
@@ -66,51 +140,22 @@ impl Rock {
         self.coord.1
     }
 
-    fn max_y(&self) -> usize {
-        self.y() + self.kind.height() - 1
-    }
-
-    fn iter(&self) -> impl Iterator<Item = (usize, usize)> {
-        let mut coords = match self.kind {
-            RockKind::A => vec![(0, 0), (1, 0), (2, 0), (3, 0)],
-            RockKind::B => vec![(0, 1), (1, 0), (1, 1), (1, 2), (2, 1)],
-            RockKind::C => vec![(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)],
-            RockKind::D => vec![(0, 0), (0, 1), (0, 2), (0, 3)],
-            RockKind::E => vec![(0, 0), (0, 1), (1, 0), (1, 1)],
-        };
-        for (x, y) in &mut coords {
-            *x += self.x();
-            *y += self.y();
+    /// Returns `true` if the rock overlaps neither the walls nor any settled
+    /// rock in `rows` of a chamber `width` columns wide.
+    fn fits(&self, rows: &[Row], width: usize) -> bool {
+        if self.x() == 0 || self.x() + self.shape.width > width + 1 || self.y() == 0 {
+            return false;
         }
-        coords.into_iter()
+        self.rows(width)
+            .zip(self.y() - 1..)
+            .all(|(mask, y)| rows.get(y).is_none_or(|row| row & mask == 0))
     }
 
-    fn occupied(&self, coord: (usize, usize)) -> bool {
-        let (x, y) = coord;
-        if x < self.coord.0
-            || x >= self.coord.0 + self.kind.width()
-            || y < self.coord.1
-            || y >= self.coord.1 + self.kind.height()
-        {
-            return false;
-        }
-        match self.kind {
-            RockKind::A | RockKind::D | RockKind::E => true,
-            RockKind::B => {
-                coord == (self.coord.0, self.coord.1 + 1)
-                    || coord == (self.coord.0 + 1, self.coord.1)
-                    || coord == (self.coord.0 + 1, self.coord.1 + 1)
-                    || coord == (self.coord.0 + 1, self.coord.1 + 2)
-                    || coord == (self.coord.0 + 2, self.coord.1 + 1)
-            }
-            RockKind::C => {
-                coord == (self.coord.0, self.coord.1)
-                    || coord == (self.coord.0 + 1, self.coord.1)
-                    || coord == (self.coord.0 + 2, self.coord.1)
-                    || coord == (self.coord.0 + 2, self.coord.1 + 1)
-                    || coord == (self.coord.0 + 2, self.coord.1 + 2)
-            }
-        }
+    /// Iterates over the rows of the rock from bottom to top, shifted to its
+    /// column in a chamber `width` columns wide.
+    fn rows(&self, width: usize) -> impl Iterator<Item = Row> + '_ {
+        let shift = width + 1 - self.x() - self.shape.width;
+        self.shape.rows.iter().map(move |row| row << shift)
     }
 
     fn move_left(&mut self) {
@@ -130,189 +175,522 @@ impl Rock {
     }
 }
 
-fn rock_collision(rock1: &Rock, rock2: &Rock) -> bool {
-    match (rock1.kind, rock2.kind) {
-        (RockKind::A, RockKind::A) => rock1.y() == rock2.y(),
-        (RockKind::D, RockKind::D) => rock1.x() == rock2.x() && rock1.y().abs_diff(rock2.y()) < 4,
-        (RockKind::E, RockKind::E) => {
-            rock1.x().abs_diff(rock2.x()) < 2 && rock1.y().abs_diff(rock2.y()) < 2
-        }
-        _ => rock1.iter().any(|coord| rock2.occupied(coord)),
-    }
-}
-
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum JetDirection {
     Left,
     Right,
 }
 
+/// The jet pattern, repeating forever.
 struct Jet {
-    pattern: String,
+    pattern: Vec<JetDirection>,
+    /// The position in the pattern of the next push.
+    index: usize,
 }
 
 impl Jet {
     fn new(pattern: &str) -> Self {
-        Self {
-            pattern: pattern.to_string(),
-        }
-    }
-
-    fn iter(&self) -> impl Iterator<Item = JetDirection> + '_ {
-        self.pattern
+        let pattern = pattern
             .chars()
             .map(|c| match c {
                 '<' => JetDirection::Left,
                 '>' => JetDirection::Right,
                 _ => panic!("invalid jet pattern"),
             })
-            .cycle()
+            .collect();
+        Self { pattern, index: 0 }
     }
 }
 
+impl Iterator for Jet {
+    type Item = JetDirection;
+
+    fn next(&mut self) -> Option<JetDirection> {
+        let dir = self.pattern[self.index];
+        self.index = (self.index + 1) % self.pattern.len();
+        Some(dir)
+    }
+}
+
+/// The chamber, with the walls at x = 0 and x = width + 1 and the floor at
+/// y = 0.
 struct Chamber {
-    rocks: Vec<Rock>,
+    config: Config,
+    /// The settled rock in each row from the floor up.
+    rows: Vec<Row>,
     heights: Vec<usize>,
-    count: usize,
+    /// The number of rows to render in each frame, if recording.
+    recording: Option<usize>,
+    /// A rendering of every step of the falling rocks, if recording.
+    frames: Vec<String>,
 }
 
 impl Chamber {
-    fn new() -> Self {
+    fn new(config: Config) -> Self {
         Self {
-            rocks: Vec::new(),
+            config,
+            rows: Vec::new(),
             heights: Vec::new(),
-            count: 0,
+            recording: None,
+            frames: Vec::new(),
         }
     }
 
-    fn add_rock(&mut self, kind: RockKind, jet: impl Iterator<Item = JetDirection>) {
-        let mut falling_rock = Rock::new(kind, (3, self.height() + 4));
+    /// Starts recording a frame with the top `rows` rows for every step.
+    fn record(&mut self, rows: usize) {
+        self.recording = Some(rows);
+    }
 
-        'falling: for dir in jet {
+    /// Drops the `index`-th rock of the configured shapes.
+    fn add_rock(&mut self, index: usize, jet: impl Iterator<Item = JetDirection>) {
+        let width = self.config.width;
+        let mut falling_rock = Rock::new(
+            &self.config.shapes[index],
+            (
+                self.config.spawn_x + 1,
+                self.height() + self.config.spawn_y + 1,
+            ),
+        );
+
+        let caption = if self.heights.is_empty() {
+            "The first rock begins falling:"
+        } else {
+            "A new rock begins falling:"
+        };
+        let frame = self.frame(caption, Some(&falling_rock));
+        self.frames.extend(frame);
+
+        for dir in jet {
             // being pushed by a jet
-            match dir {
+            let (caption, moved) = match dir {
                 JetDirection::Left => {
-                    if falling_rock.x() > 1 {
-                        falling_rock.move_left();
-                        for (rock, height) in self.rocks.iter().zip(self.heights.iter()).rev() {
-                            if *height < falling_rock.y() {
-                                break;
-                            }
-                            if rock_collision(&falling_rock, rock) {
-                                falling_rock.move_right();
-                                break;
-                            }
-                        }
+                    falling_rock.move_left();
+                    let fits = falling_rock.fits(&self.rows, width);
+                    if !fits {
+                        falling_rock.move_right();
                     }
+                    ("Jet of gas pushes rock left", fits)
                 }
                 JetDirection::Right => {
-                    if falling_rock.x() + kind.width() <= 7 {
-                        falling_rock.move_right();
-                        for (rock, height) in self.rocks.iter().zip(self.heights.iter()).rev() {
-                            if *height < falling_rock.y() {
-                                break;
-                            }
-                            if rock_collision(&falling_rock, rock) {
-                                falling_rock.move_left();
-                                break;
-                            }
-                        }
+                    falling_rock.move_right();
+                    let fits = falling_rock.fits(&self.rows, width);
+                    if !fits {
+                        falling_rock.move_left();
                     }
+                    ("Jet of gas pushes rock right", fits)
                 }
+            };
+            if self.recording.is_some() {
+                let caption = if moved {
+                    format!("{caption}:")
+                } else {
+                    format!("{caption}, but nothing happens:")
+                };
+                let frame = self.frame(&caption, Some(&falling_rock));
+                self.frames.extend(frame);
             }
 
             // falling one unit down
-            if falling_rock.y() == 1 {
+            falling_rock.move_down();
+            if !falling_rock.fits(&self.rows, width) {
+                falling_rock.move_up();
                 break;
             }
-            falling_rock.move_down();
-            for (rock, height) in self.rocks.iter().zip(self.heights.iter()).rev() {
-                if *height < falling_rock.y() {
-                    break;
-                }
-                if rock_collision(&falling_rock, rock) {
-                    falling_rock.move_up();
-                    break 'falling;
-                }
+            let frame = self.frame("Rock falls 1 unit:", Some(&falling_rock));
+            self.frames.extend(frame);
+        }
+
+        let bottom = falling_rock.y() - 1;
+        for (i, mask) in falling_rock.rows(width).enumerate() {
+            while bottom + i >= self.rows.len() {
+                self.rows.push(0);
             }
+            self.rows[bottom + i] |= mask;
         }
-        if self.heights.is_empty() {
-            self.heights.push(falling_rock.max_y());
-        } else {
-            self.heights
-                .push((*self.heights.iter().last().unwrap()).max(falling_rock.max_y()));
+        self.heights.push(self.rows.len());
+        let frame = self.frame("Rock falls 1 unit, causing it to come to rest:", None);
+        self.frames.extend(frame);
+    }
+
+    /// Renders the top `rows` rows of the chamber like the puzzle does, with
+    /// the falling rock as `@` and settled rock as `#`.
+    fn render(&self, rows: usize, falling: Option<&Rock>) -> String {
+        let width = self.config.width;
+        let top = falling
+            .map_or(0, |rock| rock.y() + rock.shape.rows.len() - 1)
+            .max(self.height());
+
+        let mut s = String::new();
+        for y in (1..=top).rev().take(rows) {
+            let settled = self.rows.get(y - 1).copied().unwrap_or(0);
+            let rock = falling
+                .filter(|rock| y >= rock.y())
+                .and_then(|rock| rock.rows(width).nth(y - rock.y()))
+                .unwrap_or(0);
+            s.push('|');
+            for x in 0..width {
+                let mask = 1 << (width - 1 - x);
+                s.push(if rock & mask != 0 {
+                    '@'
+                } else if settled & mask != 0 {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            s.push_str("|\n");
         }
-        self.count += 1;
-        self.rocks.push(falling_rock);
+        if rows > top {
+            s.push('+');
+            s.push_str(&"-".repeat(width));
+            s.push_str("+\n");
+        }
+        s
+    }
+
+    /// Returns a captioned rendering for the frames, if recording.
+    fn frame(&self, caption: &str, falling: Option<&Rock>) -> Option<String> {
+        self.recording
+            .map(|rows| format!("{caption}\n{}", self.render(rows, falling)))
     }
 
     fn height(&self) -> usize {
-        self.heights.iter().last().copied().unwrap_or(0)
+        self.rows.len()
     }
 
-    fn find_repeat(&self, period: usize) -> usize {
-        if self.rocks.len() < period {
-            return 0;
-        }
-        let src = self.rocks.len() - 1;
-        let mut dst = src;
-        while dst > period {
-            dst -= period;
-            let diff = self.rocks[src].y() - self.rocks[dst].y();
-            for i in 0..period {
-                if self.rocks[src - i].x() != self.rocks[dst - i].x()
-                    || self.rocks[src - i].y() - self.rocks[dst - i].y() != diff
-                {
+    /// Returns the air a falling rock could still reach, one row per depth
+    /// below the top of the tower, or `None` if it reaches deeper than
+    /// `max_depth`. Anything a rock cannot reach no longer affects the
+    /// simulation, so this identifies the chamber state exactly.
+    fn surface(&self, max_depth: usize) -> Option<Vec<Row>> {
+        let width = self.config.width;
+        let mut surface = Vec::new();
+        let all = Row::MAX >> (Row::BITS as usize - width);
+        let mut reached = all;
+        for row in self.rows.iter().rev() {
+            // spread sideways through the air in this row
+            reached &= !row;
+            loop {
+                let spread = reached | (reached << 1 | reached >> 1) & !row & all;
+                if spread == reached {
                     break;
                 }
-                if i == period - 1 {
-                    return src - dst;
-                }
+                reached = spread;
+            }
+            if reached == 0 {
+                return Some(surface);
             }
+            if surface.len() == max_depth {
+                return None;
+            }
+            surface.push(reached);
         }
-        0
+        Some(surface)
     }
 }
 
+/// The rocks dropped before the chamber state first repeats, and the number
+/// of rocks after which it repeats again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    start: usize,
+    length: usize,
+}
+
+/// The number of rocks to simulate while looking for a cycle before giving
+/// up.
+const MAX_SIMULATED: usize = 1_000_000;
+
+/// The deepest reachable surface worth remembering. Anything deeper is most
+/// likely a shaft growing with the tower, whose state cannot repeat.
+const MAX_SURFACE_DEPTH: usize = 1_000;
+
+/// Returns the height of the tower after `count` rocks have fallen, skipping
+/// whole cycles once the state of the chamber repeats.
+fn tower_height(config: &Config, pattern: &str, count: usize) -> Result<usize> {
+    let mut jet = Jet::new(pattern);
+    let mut chamber = Chamber::new(config.clone());
+    let shapes = config.shapes.len();
+    let mut seen = HashMap::new();
+    let mut cycle = None;
+
+    for i in 0..count {
+        if i == MAX_SIMULATED {
+            bail!("the chamber state did not repeat within {MAX_SIMULATED} rocks");
+        }
+        chamber.add_rock(i % shapes, &mut jet);
+        let Some(surface) = chamber.surface(MAX_SURFACE_DEPTH) else {
+            continue;
+        };
+        let state = ((i + 1) % shapes, jet.index, surface);
+        if let Some(start) = seen.insert(state, i + 1) {
+            cycle = Some(Cycle {
+                start,
+                length: i + 1 - start,
+            });
+            break;
+        }
+    }
+
+    let Some(Cycle { start, length }) = cycle else {
+        return Ok(chamber.height());
+    };
+    let heights = &chamber.heights;
+    let height_at = |rocks: usize| if rocks == 0 { 0 } else { heights[rocks - 1] };
+    let cycle_height = height_at(start + length) - height_at(start);
+    let cycles = (count - start) / length;
+    let remainder = (count - start) % length;
+    Ok(height_at(start + remainder) + cycles * cycle_height)
+}
+
 fn main() -> Result<()> {
     let input = io::read_to_string(io::stdin())?;
-    let period = if input.len() % 5 == 0 {
-        input.len()
-    } else {
-        input.len() * 5
-    };
-    let jet = Jet::new(input.trim());
-    let mut chamber = Chamber::new();
-    assert_eq!(chamber.height(), 0);
+    let pattern = input.trim();
+
+    // Optional flags: [--shapes <file>] [--width <n>] [--spawn-x <n>]
+    //                 [--spawn-y <n>] [--render <rows>] [--frames <file>]
+    //                 [--frame-rocks <n>] [<rock count>...]
+    let mut config = Config::default();
+    let mut counts = Vec::new();
+    let mut render = None;
+    let mut frames = None;
+    let mut frame_rocks = 10;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--shapes" => config.shapes = parse_shapes(&fs::read_to_string(value()?)?)?,
+            "--width" => config.width = value()?.parse()?,
+            "--spawn-x" => config.spawn_x = value()?.parse()?,
+            "--spawn-y" => config.spawn_y = value()?.parse()?,
+            "--render" => render = Some(value()?.parse()?),
+            "--frames" => frames = Some(value()?),
+            "--frame-rocks" => frame_rocks = value()?.parse()?,
+            _ => counts.push(arg.parse::<usize>()?),
+        }
+    }
+    config.validate()?;
+
+    if let Some(path) = frames {
+        let mut jet = Jet::new(pattern);
+        let mut chamber = Chamber::new(config.clone());
+        chamber.record(usize::MAX);
+        for i in 0..frame_rocks {
+            chamber.add_rock(i % config.shapes.len(), &mut jet);
+        }
+        fs::write(path, chamber.frames.join("\n"))?;
+    }
 
-    let mut jet_iter = jet.iter();
-    for (i, kind) in RockKind::iter().enumerate() {
-        if i == 2022 {
-            break;
+    let mut targets = vec![
+        ("Part 1".to_string(), 2022),
+        ("Part 2".to_string(), 1_000_000_000_000),
+    ];
+    targets.extend(
+        counts
+            .into_iter()
+            .map(|count| (format!("{count} rocks"), count)),
+    );
+    for (label, count) in targets {
+        match tower_height(&config, pattern, count) {
+            Ok(height) => println!("{label}: {height}"),
+            Err(e) => println!("{label}: {e}"),
         }
-        chamber.add_rock(kind, &mut jet_iter);
     }
-    println!("Part 1: {}", chamber.height());
-
-    let mut chamber = Chamber::new();
-    let mut jet_iter = jet.iter();
-    let mut final_height = 0;
-    for (i, kind) in RockKind::iter().enumerate() {
-        chamber.add_rock(kind, &mut jet_iter);
-        if i % period == period - 1 {
-            let repeat = chamber.find_repeat(period);
-            if repeat > 0 {
-                let repeat_height = chamber.heights[i] - chamber.heights[i - repeat];
-                let remaining = 1_000_000_000_000 - i - 1;
-                let cycles = remaining / repeat;
-                let remainder = remaining % repeat;
-                let remainder_height =
-                    chamber.heights[i - repeat + remainder] - chamber.heights[i - repeat];
-                final_height = chamber.heights[i] + cycles * repeat_height + remainder_height;
-                break;
-            }
+
+    if let Some(rows) = render {
+        let mut jet = Jet::new(pattern);
+        let mut chamber = Chamber::new(config.clone());
+        for i in 0..2022 {
+            chamber.add_rock(i % config.shapes.len(), &mut jet);
         }
+        print!("{}", chamber.render(rows, None));
     }
-    println!("Part 2: {final_height}");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>";
+
+    #[test]
+    fn bitboard() {
+        let mut jet = Jet::new(INPUT);
+        let mut chamber = Chamber::new(Config::default());
+        for i in 0..3 {
+            chamber.add_rock(i, &mut jet);
+        }
+        assert_eq!(
+            chamber.rows,
+            [0b001_1110, 0b000_1000, 0b001_1100, 0b111_1000, 0b001_0000, 0b001_0000]
+        );
+        assert_eq!(chamber.heights, [1, 4, 6]);
+
+        for i in 3..2022 {
+            chamber.add_rock(i % 5, &mut jet);
+        }
+        assert_eq!(chamber.height(), 3068);
+    }
+
+    #[test]
+    fn cycle() {
+        let pattern = include_str!("../input/test.txt").trim();
+        let config = Config::default();
+        assert_eq!(tower_height(&config, pattern, 0).unwrap(), 0);
+        assert_eq!(tower_height(&config, pattern, 2022).unwrap(), 3068);
+        assert_eq!(
+            tower_height(&config, pattern, 1_000_000_000_000).unwrap(),
+            1_514_285_714_288
+        );
+
+        // compare with a plain simulation
+        let mut jet = Jet::new(pattern);
+        let mut chamber = Chamber::new(config.clone());
+        for i in 0..500 {
+            chamber.add_rock(i % 5, &mut jet);
+            assert_eq!(
+                tower_height(&config, pattern, i + 1).unwrap(),
+                chamber.height()
+            );
+        }
+    }
+
+    #[test]
+    fn custom_shapes() {
+        let shapes = parse_shapes("#.\n##\n\n###\n").unwrap();
+        assert_eq!(
+            shapes,
+            [
+                Shape {
+                    rows: vec![0b11, 0b10],
+                    width: 2,
+                },
+                Shape {
+                    rows: vec![0b111],
+                    width: 3,
+                },
+            ]
+        );
+        assert!(parse_shapes("#x").is_err());
+        assert!(parse_shapes("").is_err());
+
+        let config = Config {
+            shapes,
+            width: 4,
+            spawn_x: 1,
+            spawn_y: 1,
+        };
+        config.validate().unwrap();
+        let mut jet = Jet::new(">");
+        let mut chamber = Chamber::new(config.clone());
+        chamber.add_rock(0, &mut jet);
+        chamber.add_rock(1, &mut jet);
+        assert_eq!(chamber.rows, [0b0011, 0b0010, 0b0111]);
+
+        // all rocks pushed right, so each pair of rocks adds three rows
+        assert_eq!(tower_height(&config, ">", 1000).unwrap(), 1500);
+
+        // the leftmost column is never filled, so the state never repeats
+        for i in 2..1000 {
+            chamber.add_rock(i % 2, &mut jet);
+        }
+        assert_eq!(chamber.surface(10), None);
+        assert_eq!(
+            chamber.surface(2000).map(|surface| surface.len()),
+            Some(1500)
+        );
+
+        let config = Config { width: 2, ..config };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn frames() {
+        let mut jet = Jet::new(INPUT);
+        let mut chamber = Chamber::new(Config::default());
+        chamber.record(usize::MAX);
+        chamber.add_rock(0, &mut jet);
+        chamber.add_rock(1, &mut jet);
+        assert_eq!(
+            chamber.frames[..10].join("\n"),
+            "\
+The first rock begins falling:
+|..@@@@.|
+|.......|
+|.......|
+|.......|
++-------+
+
+Jet of gas pushes rock right:
+|...@@@@|
+|.......|
+|.......|
+|.......|
++-------+
+
+Rock falls 1 unit:
+|...@@@@|
+|.......|
+|.......|
++-------+
+
+Jet of gas pushes rock right, but nothing happens:
+|...@@@@|
+|.......|
+|.......|
++-------+
+
+Rock falls 1 unit:
+|...@@@@|
+|.......|
++-------+
+
+Jet of gas pushes rock right, but nothing happens:
+|...@@@@|
+|.......|
++-------+
+
+Rock falls 1 unit:
+|...@@@@|
++-------+
+
+Jet of gas pushes rock left:
+|..@@@@.|
++-------+
+
+Rock falls 1 unit, causing it to come to rest:
+|..####.|
++-------+
+
+A new rock begins falling:
+|...@...|
+|..@@@..|
+|...@...|
+|.......|
+|.......|
+|.......|
+|..####.|
++-------+
+"
+        );
+
+        for i in 2..10 {
+            chamber.add_rock(i % 5, &mut jet);
+        }
+        assert_eq!(
+            chamber.render(3, None),
+            "\
+|....#..|
+|....#..|
+|....##.|
+"
+        );
+        assert_eq!(chamber.render(18, None).lines().count(), 18);
+        assert!(chamber.render(18, None).ends_with("|..####.|\n+-------+\n"));
+    }
+}
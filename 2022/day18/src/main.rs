@@ -1,6 +1,6 @@
-use std::{collections::HashSet, io, str::FromStr};
+use std::{collections::HashMap, env, fmt::Write, fs, io, str::FromStr, time::Instant};
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Cube {
@@ -40,115 +40,693 @@ impl FromStr for Cube {
     }
 }
 
-struct Lava {
-    cubes: Vec<Cube>,
-}
+/// The most cells a bounding box may have, about 256 cubes a side, so that
+/// flood-filling it stays within memory.
+const MAX_CELLS: usize = 1 << 24;
 
-struct Space {
-    x_range: std::ops::RangeInclusive<isize>,
-    y_range: std::ops::RangeInclusive<isize>,
-    z_range: std::ops::RangeInclusive<isize>,
+/// A set of cubes stored as one bit per cell of a bounding box.
+#[derive(Clone, Debug)]
+struct Voxels {
+    min: (isize, isize, isize),
+    size: (usize, usize, usize),
+    bits: Vec<u64>,
 }
 
-impl Space {
-    fn contains(&self, cube: &Cube) -> bool {
-        self.x_range.contains(&cube.x)
-            && self.y_range.contains(&cube.y)
-            && self.z_range.contains(&cube.z)
+impl Voxels {
+    /// Returns the number of cells from `min` to `max`, both inclusive, or
+    /// `None` if it overflows.
+    fn cells(min: (isize, isize, isize), max: (isize, isize, isize)) -> Option<usize> {
+        let side = |lo: isize, hi: isize| {
+            let len = hi.checked_sub(lo)?.checked_add(1)?.max(0);
+            usize::try_from(len).ok()
+        };
+        side(min.0, max.0)?
+            .checked_mul(side(min.1, max.1)?)?
+            .checked_mul(side(min.2, max.2)?)
+    }
+
+    /// Creates an empty set covering `min` to `max`, both inclusive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of cells overflows.
+    #[allow(clippy::cast_sign_loss)]
+    fn new(min: (isize, isize, isize), max: (isize, isize, isize)) -> Self {
+        let len = Self::cells(min, max).expect("bounding box too large");
+        let size = (
+            (max.0 - min.0 + 1).max(0) as usize,
+            (max.1 - min.1 + 1).max(0) as usize,
+            (max.2 - min.2 + 1).max(0) as usize,
+        );
+        Self {
+            min,
+            size,
+            bits: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    /// Returns the position of the cell in `bits`, or `None` if it is outside
+    /// the bounding box.
+    #[allow(clippy::cast_sign_loss)]
+    fn index(&self, x: isize, y: isize, z: isize) -> Option<usize> {
+        let (x, y, z) = (x - self.min.0, y - self.min.1, z - self.min.2);
+        if x < 0 || y < 0 || z < 0 {
+            return None;
+        }
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+        if x >= self.size.0 || y >= self.size.1 || z >= self.size.2 {
+            return None;
+        }
+        Some((z * self.size.1 + y) * self.size.0 + x)
+    }
+
+    fn contains(&self, x: isize, y: isize, z: isize) -> bool {
+        self.index(x, y, z)
+            .is_some_and(|i| self.bits[i / 64] & 1 << (i % 64) != 0)
+    }
+
+    /// Adds the cube, returning `false` if it was already present.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cube is outside the bounding box.
+    fn insert(&mut self, x: isize, y: isize, z: isize) -> bool {
+        let i = self.index(x, y, z).expect("cube outside the bounding box");
+        let present = self.bits[i / 64] & 1 << (i % 64) != 0;
+        self.bits[i / 64] |= 1 << (i % 64);
+        !present
     }
 }
 
+struct Lava {
+    cubes: Vec<Cube>,
+    voxels: Voxels,
+}
+
 impl Lava {
+    /// Stores the cubes, failing if their bounding box, with the layer of
+    /// air around it that [`Lava::steam`] needs, has too many cells.
+    fn new(cubes: Vec<Cube>) -> Result<Self> {
+        let (min, max) = bounds(&cubes);
+        let cells = Voxels::cells(
+            (
+                min.0.saturating_sub(1),
+                min.1.saturating_sub(1),
+                min.2.saturating_sub(1),
+            ),
+            (
+                max.0.saturating_add(1),
+                max.1.saturating_add(1),
+                max.2.saturating_add(1),
+            ),
+        );
+        ensure!(
+            cells.is_some_and(|cells| cells <= MAX_CELLS),
+            "the cubes from {min:?} to {max:?} span more than {MAX_CELLS} cells"
+        );
+        let mut voxels = Voxels::new(min, max);
+        for cube in &cubes {
+            voxels.insert(cube.x, cube.y, cube.z);
+        }
+        Ok(Self { cubes, voxels })
+    }
+
     fn contains(&self, x: isize, y: isize, z: isize) -> bool {
-        self.cubes
-            .iter()
-            .any(|cube| cube.x == x && cube.y == y && cube.z == z)
+        self.voxels.contains(x, y, z)
     }
 
-    fn steam(&self) -> HashSet<(isize, isize, isize)> {
-        let max_x = self.cubes.iter().map(|cube| cube.x).max().unwrap();
-        let min_x = self.cubes.iter().map(|cube| cube.x).min().unwrap();
-        let max_y = self.cubes.iter().map(|cube| cube.y).max().unwrap();
-        let min_y = self.cubes.iter().map(|cube| cube.y).min().unwrap();
-        let max_z = self.cubes.iter().map(|cube| cube.z).max().unwrap();
-        let min_z = self.cubes.iter().map(|cube| cube.z).min().unwrap();
+    /// Returns the air reachable from outside the droplet, within one cube
+    /// around its bounding box.
+    fn steam(&self) -> Voxels {
+        let (min, max) = bounds(&self.cubes);
+        let min = (min.0 - 1, min.1 - 1, min.2 - 1);
+        let max = (max.0 + 1, max.1 + 1, max.2 + 1);
 
-        let space = Space {
-            x_range: min_x - 1..=max_x + 1,
-            y_range: min_y - 1..=max_y + 1,
-            z_range: min_z - 1..=max_z + 1,
-        };
-        let steam = Cube::new(min_x - 1, min_y - 1, min_z - 1);
-        let mut visited = HashSet::new();
-        let mut to_visit = vec![steam];
+        let mut visited = Voxels::new(min, max);
+        visited.insert(min.0, min.1, min.2);
+        let mut to_visit = vec![Cube::new(min.0, min.1, min.2)];
         while let Some(cur) = to_visit.pop() {
-            if visited.contains(&(cur.x, cur.y, cur.z)) {
-                continue;
-            }
-            visited.insert((cur.x, cur.y, cur.z));
             for next in cur.neighbors() {
-                if !space.contains(&next) {
+                if visited.index(next.x, next.y, next.z).is_none() {
                     continue;
                 }
                 if self.contains(next.x, next.y, next.z) {
                     continue;
                 }
-                if visited.contains(&(next.x, next.y, next.z)) {
-                    continue;
+                if visited.insert(next.x, next.y, next.z) {
+                    to_visit.push(next);
                 }
-                to_visit.push(next.clone());
             }
         }
 
         visited
     }
+
+    /// Returns the pockets of air enclosed by the droplet.
+    fn pockets(&self) -> Vec<Component> {
+        let steam = self.steam();
+        let mut visited = Voxels::new(self.voxels.min, self.max());
+        self.components_where(&mut visited, |x, y, z| {
+            !self.contains(x, y, z) && !steam.contains(x, y, z)
+        })
+    }
+
+    /// Returns the separate pieces of the droplet.
+    fn components(&self) -> Vec<Component> {
+        let mut visited = Voxels::new(self.voxels.min, self.max());
+        self.components_where(&mut visited, |x, y, z| self.contains(x, y, z))
+    }
+
+    /// Returns the maximum coordinates of the bounding box.
+    #[allow(clippy::cast_possible_wrap)]
+    fn max(&self) -> (isize, isize, isize) {
+        let (min, size) = (self.voxels.min, self.voxels.size);
+        (
+            min.0 + size.0 as isize - 1,
+            min.1 + size.1 as isize - 1,
+            min.2 + size.2 as isize - 1,
+        )
+    }
+
+    /// Flood-fills every group of cubes in the bounding box matching `member`.
+    fn components_where(
+        &self,
+        visited: &mut Voxels,
+        member: impl Fn(isize, isize, isize) -> bool,
+    ) -> Vec<Component> {
+        let (min, max) = (self.voxels.min, self.max());
+        let mut components = Vec::new();
+        for z in min.2..=max.2 {
+            for y in min.1..=max.1 {
+                for x in min.0..=max.0 {
+                    if !member(x, y, z) || !visited.insert(x, y, z) {
+                        continue;
+                    }
+
+                    let mut component = Component {
+                        volume: 0,
+                        surface_area: 0,
+                        bounds: ((x, y, z), (x, y, z)),
+                    };
+                    let mut to_visit = vec![Cube::new(x, y, z)];
+                    while let Some(cur) = to_visit.pop() {
+                        let (lo, hi) = &mut component.bounds;
+                        *lo = (lo.0.min(cur.x), lo.1.min(cur.y), lo.2.min(cur.z));
+                        *hi = (hi.0.max(cur.x), hi.1.max(cur.y), hi.2.max(cur.z));
+                        component.volume += 1;
+                        for next in cur.neighbors() {
+                            if !member(next.x, next.y, next.z) {
+                                component.surface_area += 1;
+                            } else if visited.insert(next.x, next.y, next.z) {
+                                to_visit.push(next);
+                            }
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+
+    /// Returns the number of cube faces not touching another cube.
+    fn surface_area(&self) -> usize {
+        self.cubes
+            .iter()
+            .flat_map(Cube::neighbors)
+            .filter(|n| !self.contains(n.x, n.y, n.z))
+            .count()
+    }
+
+    /// Returns the number of cube faces touching the outside air.
+    fn exterior_surface_area(&self) -> usize {
+        let steam = self.steam();
+        self.cubes
+            .iter()
+            .flat_map(Cube::neighbors)
+            .filter(|n| steam.contains(n.x, n.y, n.z))
+            .count()
+    }
+}
+
+/// A face-connected group of cubes, either of lava or of air.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Component {
+    /// The number of cubes.
+    volume: usize,
+    /// The number of faces touching anything outside the group.
+    surface_area: usize,
+    /// The minimum and maximum coordinates of the cubes.
+    bounds: ((isize, isize, isize), (isize, isize, isize)),
+}
+
+/// Returns the minimum and maximum coordinates of the cubes.
+fn bounds(cubes: &[Cube]) -> ((isize, isize, isize), (isize, isize, isize)) {
+    if cubes.is_empty() {
+        return ((0, 0, 0), (-1, -1, -1));
+    }
+    let mut min = (isize::MAX, isize::MAX, isize::MAX);
+    let mut max = (isize::MIN, isize::MIN, isize::MIN);
+    for cube in cubes {
+        min = (min.0.min(cube.x), min.1.min(cube.y), min.2.min(cube.z));
+        max = (max.0.max(cube.x), max.1.max(cube.y), max.2.max(cube.z));
+    }
+    (min, max)
+}
+
+/// Generates a ball of cubes with the given radius, with a tenth of them
+/// missing at random so that it has pockets of air inside.
+fn generate(radius: isize) -> Vec<Cube> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut cubes = Vec::new();
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                // xorshift
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                if x * x + y * y + z * z <= radius * radius && !state.is_multiple_of(10) {
+                    cubes.push(Cube::new(x, y, z));
+                }
+            }
+        }
+    }
+    cubes
+}
+
+/// Times both parts on a generated droplet of the given radius.
+fn bench(radius: isize) -> Result<()> {
+    let start = Instant::now();
+    let lava = Lava::new(generate(radius))?;
+    println!(
+        "radius {radius}: {} cubes, built in {:?}",
+        lava.cubes.len(),
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let surface_area = lava.surface_area();
+    println!("  Part 1: {surface_area} in {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let surface_area = lava.exterior_surface_area();
+    println!("  Part 2: {surface_area} in {:?}", start.elapsed());
+    Ok(())
+}
+
+/// A rectangle on the surface of the droplet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Quad {
+    /// The corners, counterclockwise when seen from outside.
+    corners: [[isize; 3]; 4],
+    /// The outward normal, a unit vector along one axis.
+    normal: [isize; 3],
+    /// The number of cube faces merged into the rectangle.
+    area: usize,
+}
+
+/// The exterior surface of the droplet, with coplanar faces merged into
+/// rectangles.
+struct Mesh {
+    quads: Vec<Quad>,
+}
+
+impl Mesh {
+    fn new(lava: &Lava) -> Self {
+        let steam = lava.steam();
+        let mut quads = Vec::new();
+        for axis in 0..3 {
+            for sign in [-1, 1] {
+                let (min, max) = (lava.voxels.min, lava.max());
+                let (min, max) = ([min.0, min.1, min.2], [max.0, max.1, max.2]);
+                for layer in min[axis]..=max[axis] {
+                    let plane = Plane {
+                        axis,
+                        sign,
+                        layer,
+                        min,
+                        max,
+                    };
+                    plane.merge(lava, &steam, &mut quads);
+                }
+            }
+        }
+        Self { quads }
+    }
+
+    /// Returns the number of cube faces covered by the mesh.
+    fn area(&self) -> usize {
+        self.quads.iter().map(|quad| quad.area).sum()
+    }
+
+    /// Writes the mesh as a Wavefront OBJ file.
+    fn to_obj(&self) -> String {
+        let mut vertices = HashMap::new();
+        let mut s = String::from("# day18 droplet\n");
+        let mut faces = String::new();
+        for quad in &self.quads {
+            faces.push('f');
+            for corner in quad.corners {
+                let next = vertices.len() + 1;
+                let index = *vertices.entry(corner).or_insert_with(|| {
+                    writeln!(s, "v {} {} {}", corner[0], corner[1], corner[2]).unwrap();
+                    next
+                });
+                write!(faces, " {index}").unwrap();
+            }
+            faces.push('\n');
+        }
+        s + &faces
+    }
+
+    /// Writes the mesh as an ASCII STL file, splitting each rectangle in two
+    /// triangles.
+    fn to_stl(&self) -> String {
+        let mut s = String::from("solid droplet\n");
+        for quad in &self.quads {
+            let corners = quad.corners;
+            for triangle in [[0, 1, 2], [0, 2, 3]].map(|t| t.map(|i| corners[i])) {
+                let [nx, ny, nz] = quad.normal;
+                writeln!(s, "  facet normal {nx} {ny} {nz}").unwrap();
+                s.push_str("    outer loop\n");
+                for [x, y, z] in triangle {
+                    writeln!(s, "      vertex {x} {y} {z}").unwrap();
+                }
+                s.push_str("    endloop\n");
+                s.push_str("  endfacet\n");
+            }
+        }
+        s.push_str("endsolid droplet\n");
+        s
+    }
+}
+
+/// One layer of cube faces pointing the same way.
+struct Plane {
+    /// The axis the faces point along.
+    axis: usize,
+    /// Whether the faces point towards the positive or negative side.
+    sign: isize,
+    /// The coordinate of the cubes along `axis`.
+    layer: isize,
+    min: [isize; 3],
+    max: [isize; 3],
+}
+
+impl Plane {
+    /// Returns the two other axes, ordered so that u × v points along `axis`.
+    fn uv(&self) -> (usize, usize) {
+        ((self.axis + 1) % 3, (self.axis + 2) % 3)
+    }
+
+    /// Returns the point at the given offsets along u and v from the corner
+    /// of the bounding box, at `along` on the axis.
+    #[allow(clippy::cast_possible_wrap)]
+    fn point(&self, along: isize, offset: (usize, usize)) -> [isize; 3] {
+        let (u, v) = self.uv();
+        let mut point = [0; 3];
+        point[self.axis] = along;
+        point[u] = self.min[u] + offset.0 as isize;
+        point[v] = self.min[v] + offset.1 as isize;
+        point
+    }
+
+    /// Merges the exterior faces in this layer into rectangles.
+    #[allow(clippy::cast_sign_loss)]
+    fn merge(&self, lava: &Lava, steam: &Voxels, quads: &mut Vec<Quad>) {
+        let (u, v) = self.uv();
+        let width = (self.max[u] - self.min[u] + 1).max(0) as usize;
+        let height = (self.max[v] - self.min[v] + 1).max(0) as usize;
+
+        let mut faces = vec![false; width * height];
+        for row in 0..height {
+            for col in 0..width {
+                let cube = self.point(self.layer, (col, row));
+                let next = self.point(self.layer + self.sign, (col, row));
+                faces[row * width + col] = lava.contains(cube[0], cube[1], cube[2])
+                    && steam.contains(next[0], next[1], next[2]);
+            }
+        }
+
+        // greedily grow rectangles along u, then along v
+        let outer = if self.sign > 0 {
+            self.layer + 1
+        } else {
+            self.layer
+        };
+        for row in 0..height {
+            for col in 0..width {
+                if !faces[row * width + col] {
+                    continue;
+                }
+                let cols = (col..width).take_while(|&c| faces[row * width + c]).count();
+                let rows = (row..height)
+                    .take_while(|&r| (col..col + cols).all(|c| faces[r * width + c]))
+                    .count();
+                for r in row..row + rows {
+                    faces[r * width + col..r * width + col + cols].fill(false);
+                }
+
+                let mut corners = [
+                    self.point(outer, (col, row)),
+                    self.point(outer, (col + cols, row)),
+                    self.point(outer, (col + cols, row + rows)),
+                    self.point(outer, (col, row + rows)),
+                ];
+                if self.sign < 0 {
+                    corners.reverse();
+                }
+                let mut normal = [0; 3];
+                normal[self.axis] = self.sign;
+                quads.push(Quad {
+                    corners,
+                    normal,
+                    area: cols * rows,
+                });
+            }
+        }
+    }
+}
+
+/// Prints a table of the components and their totals.
+fn print_components(title: &str, components: &[Component]) {
+    println!("{title}: {}", components.len());
+    for c in components {
+        let (min, max) = c.bounds;
+        println!(
+            "  volume {:5}  surface {:5}  from {:?} to {:?}",
+            c.volume, c.surface_area, min, max
+        );
+    }
+    println!(
+        "  total volume {}, total surface {}",
+        components.iter().map(|c| c.volume).sum::<usize>(),
+        components.iter().map(|c| c.surface_area).sum::<usize>()
+    );
 }
 
 fn main() -> Result<()> {
+    // Optional flags: --bench <radius>... | [--pockets] [--obj <file>]
+    //                 [--stl <file>]
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|arg| arg == "--bench") {
+        for radius in &args[1..] {
+            bench(radius.parse()?)?;
+        }
+        return Ok(());
+    }
+    let show_pockets = args.iter().any(|arg| arg == "--pockets");
+    let value = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+    };
+    let obj = value("--obj");
+    let stl = value("--stl");
+
     let input = io::read_to_string(io::stdin())?;
     let lines = input.lines().collect::<Vec<_>>();
     let cubes = lines
         .iter()
         .map(|&s| s.parse::<Cube>().unwrap())
         .collect::<Vec<_>>();
-    let lava = Lava { cubes };
+    let lava = Lava::new(cubes)?;
 
-    let mut surface_area = 0;
-    for cube in &lava.cubes {
-        let mut sides = 6;
-        if lava.contains(cube.x - 1, cube.y, cube.z) {
-            sides -= 1;
-        }
-        if lava.contains(cube.x + 1, cube.y, cube.z) {
-            sides -= 1;
-        }
-        if lava.contains(cube.x, cube.y - 1, cube.z) {
-            sides -= 1;
-        }
-        if lava.contains(cube.x, cube.y + 1, cube.z) {
-            sides -= 1;
-        }
-        if lava.contains(cube.x, cube.y, cube.z - 1) {
-            sides -= 1;
+    println!("Part 1: {}", lava.surface_area());
+    println!("Part 2: {}", lava.exterior_surface_area());
+
+    if show_pockets {
+        let pockets = lava.pockets();
+        print_components("Pieces", &lava.components());
+        print_components("Pockets", &pockets);
+
+        let total = lava.surface_area();
+        let enclosed = pockets.iter().map(|c| c.surface_area).sum::<usize>();
+        println!(
+            "Exterior check: {total} - {enclosed} = {}",
+            total - enclosed
+        );
+    }
+
+    if obj.is_some() || stl.is_some() {
+        let mesh = Mesh::new(&lava);
+        println!(
+            "Mesh: {} rectangles covering {} faces",
+            mesh.quads.len(),
+            mesh.area()
+        );
+        if let Some(path) = obj {
+            fs::write(path, mesh.to_obj())?;
         }
-        if lava.contains(cube.x, cube.y, cube.z + 1) {
-            sides -= 1;
+        if let Some(path) = stl {
+            fs::write(path, mesh.to_stl())?;
         }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        surface_area += sides;
+    fn parse(input: &str) -> Lava {
+        Lava::new(input.lines().map(|s| s.parse().unwrap()).collect()).unwrap()
     }
-    println!("Part 1: {surface_area}");
 
-    let steam = lava.steam();
-    let mut contacting_steam = 0;
-    for cube in &lava.cubes {
-        for neighbor in cube.neighbors() {
-            if steam.contains(&(neighbor.x, neighbor.y, neighbor.z)) {
-                contacting_steam += 1;
+    #[test]
+    fn example() {
+        let lava = parse(include_str!("../input/test.txt"));
+        assert_eq!(lava.surface_area(), 64);
+        assert_eq!(lava.exterior_surface_area(), 58);
+
+        let lava = parse("1,1,1\n2,1,1");
+        assert_eq!(lava.surface_area(), 10);
+        assert_eq!(lava.exterior_surface_area(), 10);
+    }
+
+    #[test]
+    fn pockets() {
+        let lava = parse(include_str!("../input/test.txt"));
+        assert_eq!(
+            lava.pockets(),
+            [Component {
+                volume: 1,
+                surface_area: 6,
+                bounds: ((2, 2, 5), (2, 2, 5)),
+            }]
+        );
+        let pieces = lava.components();
+        assert_eq!(pieces.iter().map(|c| c.volume).sum::<usize>(), 13);
+        assert_eq!(pieces.iter().map(|c| c.surface_area).sum::<usize>(), 64);
+
+        // a hollow 3x3x3 cube next to a separate cube
+        let mut cubes = Vec::new();
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    if (x, y, z) != (1, 1, 1) {
+                        cubes.push(Cube::new(x, y, z));
+                    }
+                }
             }
         }
+        cubes.push(Cube::new(5, 0, 0));
+        let lava = Lava::new(cubes).unwrap();
+        assert_eq!(
+            lava.components(),
+            [
+                Component {
+                    volume: 26,
+                    surface_area: 60,
+                    bounds: ((0, 0, 0), (2, 2, 2)),
+                },
+                Component {
+                    volume: 1,
+                    surface_area: 6,
+                    bounds: ((5, 0, 0), (5, 0, 0)),
+                },
+            ]
+        );
+        assert_eq!(lava.pockets().len(), 1);
+        assert_eq!(lava.exterior_surface_area(), 60);
     }
-    println!("Part 2: {contacting_steam}");
 
-    Ok(())
+    #[test]
+    fn voxels() {
+        let mut voxels = Voxels::new((-1, 2, 3), (1, 4, 5));
+        assert!(!voxels.contains(0, 3, 4));
+        assert!(voxels.insert(0, 3, 4));
+        assert!(!voxels.insert(0, 3, 4));
+        assert!(voxels.contains(0, 3, 4));
+        assert!(!voxels.contains(-2, 3, 4));
+        assert!(!voxels.contains(0, 3, 6));
+        assert_eq!(voxels.index(-1, 2, 3), Some(0));
+        assert_eq!(voxels.index(1, 4, 5), Some(26));
+
+        assert_eq!(Voxels::cells((-1, 2, 3), (1, 4, 5)), Some(27));
+        assert_eq!(Voxels::cells((0, 0, 0), (-1, -1, -1)), Some(0));
+        assert_eq!(Voxels::cells((isize::MIN, 0, 0), (isize::MAX, 0, 0)), None);
+        let error = Lava::new(vec![Cube::new(0, 0, 0), Cube::new(5000, 5000, 5000)])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "the cubes from (0, 0, 0) to (5000, 5000, 5000) span more than 16777216 cells"
+        );
+    }
+
+    #[test]
+    fn generated() {
+        // compare with a plain scan over the cubes
+        let lava = Lava::new(generate(6)).unwrap();
+        let scan = |x, y, z| {
+            lava.cubes
+                .iter()
+                .any(|cube| cube.x == x && cube.y == y && cube.z == z)
+        };
+        let surface_area = lava
+            .cubes
+            .iter()
+            .flat_map(Cube::neighbors)
+            .filter(|n| !scan(n.x, n.y, n.z))
+            .count();
+        assert_eq!(lava.surface_area(), surface_area);
+
+        let pockets = lava.pockets().iter().map(|c| c.surface_area).sum::<usize>();
+        assert!(pockets > 0);
+        assert_eq!(lava.exterior_surface_area(), surface_area - pockets);
+    }
+
+    #[test]
+    fn mesh() {
+        let lava = parse(include_str!("../input/test.txt"));
+        assert_eq!(Mesh::new(&lava).area(), 58);
+
+        let lava = parse("1,1,1\n2,1,1");
+        let mesh = Mesh::new(&lava);
+        assert_eq!(mesh.quads.len(), 6);
+        assert_eq!(mesh.area(), 10);
+        assert!(mesh.quads.contains(&Quad {
+            corners: [[1, 1, 2], [3, 1, 2], [3, 2, 2], [1, 2, 2]],
+            normal: [0, 0, 1],
+            area: 2,
+        }));
+        assert!(mesh.quads.contains(&Quad {
+            corners: [[1, 2, 1], [3, 2, 1], [3, 1, 1], [1, 1, 1]],
+            normal: [0, 0, -1],
+            area: 2,
+        }));
+
+        let obj = mesh.to_obj();
+        assert_eq!(obj.lines().filter(|line| line.starts_with("v ")).count(), 8);
+        assert_eq!(obj.lines().filter(|line| line.starts_with("f ")).count(), 6);
+        let stl = mesh.to_stl();
+        assert_eq!(stl.matches("facet normal").count(), 12);
+        assert!(stl.starts_with("solid droplet\n  facet normal -1 0 0\n"));
+
+        let lava = Lava::new(generate(6)).unwrap();
+        assert_eq!(Mesh::new(&lava).area(), lava.exterior_surface_area());
+    }
 }
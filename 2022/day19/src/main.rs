@@ -1,75 +1,301 @@
-use std::{io, str::FromStr};
+use std::{
+    collections::HashSet,
+    env,
+    fmt::Write,
+    io,
+    num::NonZeroUsize,
+    ops::{Index, IndexMut},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Result};
 
-struct Cost {
-    ore: usize,
-    clay: usize,
-    obsidian: usize,
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Resource {
+    Ore,
+    Clay,
+    Obsidian,
+    Geode,
 }
 
-impl Cost {
-    fn new(ore: usize, clay: usize, obsidian: usize) -> Self {
-        Self {
-            ore,
-            clay,
-            obsidian,
+impl Resource {
+    const ALL: [Resource; 4] = [
+        Resource::Ore,
+        Resource::Clay,
+        Resource::Obsidian,
+        Resource::Geode,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Resource::Ore => "ore",
+            Resource::Clay => "clay",
+            Resource::Obsidian => "obsidian",
+            Resource::Geode => "geode",
+        }
+    }
+
+    /// Returns the name of the robot collecting the resource.
+    fn robot(self) -> &'static str {
+        match self {
+            Resource::Ore => "ore-collecting robot",
+            Resource::Clay => "clay-collecting robot",
+            Resource::Obsidian => "obsidian-collecting robot",
+            Resource::Geode => "geode-cracking robot",
+        }
+    }
+
+    /// Returns the indefinite article for the robot.
+    fn article(self) -> &'static str {
+        match self {
+            Resource::Ore | Resource::Obsidian => "an",
+            Resource::Clay | Resource::Geode => "a",
+        }
+    }
+}
+
+impl FromStr for Resource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ore" => Ok(Resource::Ore),
+            "clay" => Ok(Resource::Clay),
+            "obsidian" => Ok(Resource::Obsidian),
+            "geode" => Ok(Resource::Geode),
+            _ => Err(anyhow!("unknown resource {s:?}")),
         }
     }
 }
 
+/// An amount of each resource.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+struct Resources([usize; 4]);
+
+impl Resources {
+    /// Returns `true` if there is at least as much of every resource as in
+    /// `other`.
+    fn covers(&self, other: &Resources) -> bool {
+        self.0.iter().zip(other.0).all(|(&a, b)| a >= b)
+    }
+}
+
+impl Index<Resource> for Resources {
+    type Output = usize;
+
+    fn index(&self, resource: Resource) -> &usize {
+        &self.0[resource as usize]
+    }
+}
+
+impl IndexMut<Resource> for Resources {
+    fn index_mut(&mut self, resource: Resource) -> &mut usize {
+        &mut self.0[resource as usize]
+    }
+}
+
 struct Blueprint {
-    ore_robot_cost: Cost,
-    clay_robot_cost: Cost,
-    obsidian_robot_cost: Cost,
-    geode_robot_cost: Cost,
+    id: usize,
+    /// The cost of a robot collecting each resource.
+    costs: [Resources; 4],
 }
 
 impl Blueprint {
-    fn simulate_dfs(&self, env: &Env, duration: usize, mut max_seen: usize) -> usize {
-        if env.minutes == duration {
-            return env.geode;
-        }
-        if self.geode_upper_bound(env, duration) < max_seen {
-            return 0;
-        }
+    fn cost(&self, robot: Resource) -> &Resources {
+        &self.costs[robot as usize]
+    }
 
-        let mut new_env = Self::collect(env);
-        new_env.minutes += 1;
+    /// Returns the most geodes that can be opened from `env` within
+    /// `duration` minutes.
+    fn simulate_dfs(&self, env: &Env, duration: usize) -> Outcome {
+        let mut search = Search {
+            blueprint: self,
+            duration,
+            max_robots: self.max_robots(),
+            seen: HashSet::new(),
+            path: Vec::new(),
+            outcome: Outcome::default(),
+        };
+        search.visit(env);
+        search.outcome
+    }
+
+    /// Returns the most robots of each kind worth having: a factory builds one
+    /// robot a minute, so more than the largest cost of a resource is never
+    /// spent.
+    fn max_robots(&self) -> Resources {
+        let mut max = Resources([usize::MAX; 4]);
+        for resource in Resource::ALL {
+            if resource != Resource::Geode {
+                max[resource] = self.costs.iter().map(|cost| cost[resource]).max().unwrap();
+            }
+        }
+        max
+    }
 
-        if self.can_build_geode_robot(env) {
-            let new_env = self.build_geode_robot(&new_env);
-            let tree_max = self.simulate_dfs(&new_env, duration, max_seen);
-            if tree_max > max_seen {
-                max_seen = tree_max;
+    /// Returns the number of minutes to wait until the robot is affordable,
+    /// or `None` if the current robots never collect enough.
+    fn wait_for(&self, env: &Env, robot: Resource) -> Option<usize> {
+        let mut wait = 0;
+        for resource in Resource::ALL {
+            let missing = self.cost(robot)[resource].saturating_sub(env.stock[resource]);
+            if missing > 0 {
+                let rate = env.robots[resource];
+                if rate == 0 {
+                    return None;
+                }
+                wait = wait.max(missing.div_ceil(rate));
             }
         }
+        Some(wait)
+    }
 
-        if self.can_build_obsidian_robot(env) {
-            let new_env = self.build_obsidian_robot(&new_env);
-            let tree_max = self.simulate_dfs(&new_env, duration, max_seen);
-            if tree_max > max_seen {
-                max_seen = tree_max;
+    /// Returns an upper bound of the geodes opened by the end, assuming ore is
+    /// free, a clay robot is built every minute, and obsidian and geode
+    /// robots are built whenever there is enough clay or obsidian.
+    fn geode_upper_bound(&self, env: &Env, duration: usize) -> usize {
+        let obsidian_cost = self.cost(Resource::Obsidian)[Resource::Clay];
+        let geode_cost = self.cost(Resource::Geode)[Resource::Obsidian];
+        let mut stock = env.stock;
+        let mut robots = env.robots;
+        for _ in env.minutes..duration {
+            let build_geode = stock[Resource::Obsidian] >= geode_cost;
+            let build_obsidian = stock[Resource::Clay] >= obsidian_cost;
+            for resource in Resource::ALL {
+                stock[resource] += robots[resource];
             }
+            if build_geode {
+                stock[Resource::Obsidian] -= geode_cost;
+                robots[Resource::Geode] += 1;
+            }
+            if build_obsidian {
+                stock[Resource::Clay] -= obsidian_cost;
+                robots[Resource::Obsidian] += 1;
+            }
+            robots[Resource::Clay] += 1;
         }
+        stock[Resource::Geode]
+    }
+
+    /// Replays the builds minute by minute, returning the geodes opened and
+    /// the story of each minute told like the puzzle does.
+    fn replay(&self, builds: &[Build], duration: usize) -> Result<(usize, String)> {
+        let mut env = Env::new();
+        let mut builds = builds.iter().peekable();
+        let mut story = String::new();
+        while env.minutes < duration {
+            env.minutes += 1;
+            writeln!(story, "== Minute {} ==", env.minutes).unwrap();
+
+            let build = builds.next_if(|build| build.minute == env.minutes);
+            if let Some(build) = build {
+                ensure!(
+                    self.can_build(&env, build.robot),
+                    "minute {}: cannot afford the {}",
+                    env.minutes,
+                    build.robot.robot()
+                );
+                let cost = self.cost(build.robot);
+                let spent = Resource::ALL
+                    .into_iter()
+                    .filter(|&resource| cost[resource] > 0)
+                    .map(|resource| format!("{} {}", cost[resource], resource.name()))
+                    .collect::<Vec<_>>();
+                writeln!(
+                    story,
+                    "Spend {} to start building {} {}.",
+                    spent.join(" and "),
+                    build.robot.article(),
+                    build.robot.robot()
+                )
+                .unwrap();
+                for resource in Resource::ALL {
+                    env.stock[resource] -= cost[resource];
+                }
+            }
+
+            for resource in Resource::ALL {
+                let robots = env.robots[resource];
+                if robots == 0 {
+                    continue;
+                }
+                env.stock[resource] += robots;
+                let robot = resource.robot();
+                let stock = env.stock[resource];
+                if resource == Resource::Geode {
+                    let (s, verb) = if robots == 1 {
+                        ("", "cracks")
+                    } else {
+                        ("s", "crack")
+                    };
+                    let plural = |n| if n == 1 { "" } else { "s" };
+                    writeln!(
+                        story,
+                        "{robots} {robot}{s} {verb} {robots} geode{}; you now have {stock} open geode{}.",
+                        plural(robots),
+                        plural(stock)
+                    )
+                    .unwrap();
+                } else {
+                    let (s, verb) = if robots == 1 {
+                        ("", "collects")
+                    } else {
+                        ("s", "collect")
+                    };
+                    writeln!(
+                        story,
+                        "{robots} {robot}{s} {verb} {robots} {name}; you now have {stock} {name}.",
+                        name = resource.name()
+                    )
+                    .unwrap();
+                }
+            }
 
-        if self.can_build_clay_robot(env) {
-            let new_env = self.build_clay_robot(&new_env);
-            let tree_max = self.simulate_dfs(&new_env, duration, max_seen);
-            if tree_max > max_seen {
-                max_seen = tree_max;
+            if let Some(build) = build {
+                env.robots[build.robot] += 1;
+                writeln!(
+                    story,
+                    "The new {} is ready; you now have {} of them.",
+                    build.robot.robot(),
+                    env.robots[build.robot]
+                )
+                .unwrap();
             }
+            story.push('\n');
+        }
+        if let Some(build) = builds.next() {
+            bail!("unexpected build in minute {}", build.minute);
+        }
+        Ok((env.stock[Resource::Geode], story))
+    }
+
+    /// Searches minute by minute, branching on every affordable robot and on
+    /// waiting. Kept as a reference for the benchmark and tests.
+    fn simulate_minutewise(&self, env: &Env, duration: usize, mut max_seen: usize) -> usize {
+        if env.minutes == duration {
+            return env.stock[Resource::Geode];
+        }
+        if self.minutewise_upper_bound(env, duration) < max_seen {
+            return 0;
         }
 
-        if self.can_build_ore_robot(env) {
-            let new_env = self.build_ore_robot(&new_env);
-            let tree_max = self.simulate_dfs(&new_env, duration, max_seen);
-            if tree_max > max_seen {
-                max_seen = tree_max;
+        let mut new_env = Self::collect(env);
+        new_env.minutes += 1;
+
+        for robot in Resource::ALL.into_iter().rev() {
+            if self.can_build(env, robot) {
+                let new_env = self.build(&new_env, robot);
+                let tree_max = self.simulate_minutewise(&new_env, duration, max_seen);
+                if tree_max > max_seen {
+                    max_seen = tree_max;
+                }
             }
         }
 
-        let tree_max = self.simulate_dfs(&new_env, duration, max_seen);
+        let tree_max = self.simulate_minutewise(&new_env, duration, max_seen);
         if tree_max > max_seen {
             max_seen = tree_max;
         }
@@ -77,170 +303,490 @@ impl Blueprint {
         max_seen
     }
 
-    fn geode_upper_bound(&self, env: &Env, duration: usize) -> usize {
+    fn minutewise_upper_bound(&self, env: &Env, duration: usize) -> usize {
+        let geode_cost = self.cost(Resource::Geode)[Resource::Obsidian];
         let mut cur_env = env.clone();
-        let mut new_geode_robots = cur_env.geode_robots;
+        let mut new_geode_robots = cur_env.robots[Resource::Geode];
         while cur_env.minutes < duration {
-            if cur_env.obsidian >= self.geode_robot_cost.obsidian {
-                cur_env.obsidian -= self.geode_robot_cost.obsidian;
+            if cur_env.stock[Resource::Obsidian] >= geode_cost {
+                cur_env.stock[Resource::Obsidian] -= geode_cost;
                 new_geode_robots += 1;
             }
-            cur_env.geode += cur_env.geode_robots;
-            cur_env.geode_robots = new_geode_robots;
-            cur_env.obsidian += cur_env.obsidian_robots;
-            cur_env.obsidian_robots += 1; // assumption
+            cur_env.stock[Resource::Geode] += cur_env.robots[Resource::Geode];
+            cur_env.robots[Resource::Geode] = new_geode_robots;
+            cur_env.stock[Resource::Obsidian] += cur_env.robots[Resource::Obsidian];
+            cur_env.robots[Resource::Obsidian] += 1; // assumption
             cur_env.minutes += 1;
         }
-        cur_env.geode
+        cur_env.stock[Resource::Geode]
     }
 
     fn collect(env: &Env) -> Env {
         let mut env = env.clone();
-        env.ore += env.ore_robots;
-        env.clay += env.clay_robots;
-        env.obsidian += env.obsidian_robots;
-        env.geode += env.geode_robots;
+        for resource in Resource::ALL {
+            env.stock[resource] += env.robots[resource];
+        }
         env
     }
 
-    fn can_build_ore_robot(&self, env: &Env) -> bool {
-        self.ore_robot_cost.ore <= env.ore
-            && self.ore_robot_cost.clay <= env.clay
-            && self.ore_robot_cost.obsidian <= env.obsidian
+    fn can_build(&self, env: &Env, robot: Resource) -> bool {
+        env.stock.covers(self.cost(robot))
     }
 
-    fn can_build_clay_robot(&self, env: &Env) -> bool {
-        self.clay_robot_cost.ore <= env.ore
-            && self.clay_robot_cost.clay <= env.clay
-            && self.clay_robot_cost.obsidian <= env.obsidian
+    fn build(&self, env: &Env, robot: Resource) -> Env {
+        let mut env = env.clone();
+        for resource in Resource::ALL {
+            env.stock[resource] -= self.cost(robot)[resource];
+        }
+        env.robots[robot] += 1;
+        env
     }
+}
 
-    fn can_build_obsidian_robot(&self, env: &Env) -> bool {
-        self.obsidian_robot_cost.ore <= env.ore
-            && self.obsidian_robot_cost.clay <= env.clay
-            && self.obsidian_robot_cost.obsidian <= env.obsidian
-    }
+/// The result of searching a blueprint.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Outcome {
+    /// The most geodes opened.
+    geodes: usize,
+    /// The number of states explored.
+    nodes: usize,
+    /// The robots to build to open the most geodes.
+    builds: Vec<Build>,
+}
 
-    fn can_build_geode_robot(&self, env: &Env) -> bool {
-        self.geode_robot_cost.ore <= env.ore
-            && self.geode_robot_cost.clay <= env.clay
-            && self.geode_robot_cost.obsidian <= env.obsidian
-    }
+/// A robot to build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Build {
+    /// The minute during which the robot is built, starting from 1.
+    minute: usize,
+    robot: Resource,
+}
 
-    fn build_ore_robot(&self, env: &Env) -> Env {
-        let mut env = env.clone();
-        env.ore -= self.ore_robot_cost.ore;
-        env.clay -= self.ore_robot_cost.clay;
-        env.obsidian -= self.ore_robot_cost.obsidian;
-        env.ore_robots += 1;
-        env
-    }
+/// A depth-first search jumping from one robot build to the next.
+struct Search<'a> {
+    blueprint: &'a Blueprint,
+    duration: usize,
+    max_robots: Resources,
+    /// The states already explored. Their best is already known, or could not
+    /// beat it when they were pruned.
+    seen: HashSet<Env>,
+    /// The robots built on the way to the current state.
+    path: Vec<Build>,
+    outcome: Outcome,
+}
 
-    fn build_clay_robot(&self, env: &Env) -> Env {
-        let mut env = env.clone();
-        env.ore -= self.clay_robot_cost.ore;
-        env.clay -= self.clay_robot_cost.clay;
-        env.obsidian -= self.clay_robot_cost.obsidian;
-        env.clay_robots += 1;
-        env
-    }
+impl Search<'_> {
+    fn visit(&mut self, env: &Env) {
+        if !self.seen.insert(env.clone()) {
+            return;
+        }
+        self.outcome.nodes += 1;
 
-    fn build_obsidian_robot(&self, env: &Env) -> Env {
-        let mut env = env.clone();
-        env.ore -= self.obsidian_robot_cost.ore;
-        env.clay -= self.obsidian_robot_cost.clay;
-        env.obsidian -= self.obsidian_robot_cost.obsidian;
-        env.obsidian_robots += 1;
-        env
-    }
+        // waiting until the end
+        let remaining = self.duration - env.minutes;
+        let geodes = env.stock[Resource::Geode] + env.robots[Resource::Geode] * remaining;
+        if geodes > self.outcome.geodes {
+            self.outcome.geodes = geodes;
+            self.outcome.builds.clone_from(&self.path);
+        }
+        if self.blueprint.geode_upper_bound(env, self.duration) <= self.outcome.geodes {
+            return;
+        }
 
-    fn build_geode_robot(&self, env: &Env) -> Env {
-        let mut env = env.clone();
-        env.ore -= self.geode_robot_cost.ore;
-        env.clay -= self.geode_robot_cost.clay;
-        env.obsidian -= self.geode_robot_cost.obsidian;
-        env.geode_robots += 1;
-        env
+        for robot in Resource::ALL.into_iter().rev() {
+            if env.robots[robot] >= self.max_robots[robot] {
+                continue;
+            }
+            let Some(wait) = self.blueprint.wait_for(env, robot) else {
+                continue;
+            };
+            // a robot finished in the last minute collects nothing
+            if env.minutes + wait + 1 >= self.duration {
+                continue;
+            }
+
+            let mut new_env = env.clone();
+            for _ in 0..=wait {
+                new_env = Blueprint::collect(&new_env);
+            }
+            new_env.minutes += wait + 1;
+            let new_env = self.blueprint.build(&new_env, robot);
+            self.path.push(Build {
+                minute: new_env.minutes,
+                robot,
+            });
+            self.visit(&new_env);
+            self.path.pop();
+        }
     }
 }
 
 impl FromStr for Blueprint {
     type Err = anyhow::Error;
 
+    /// Parses a blueprint sentence, in any order and spacing:
+    /// `Blueprint <id>: Each <robot> robot costs <n> <resource> [and <n>
+    /// <resource>]... .`
     fn from_str(s: &str) -> Result<Self> {
-        let tokens = s.split(' ').collect::<Vec<_>>();
-        let ore_robot_cost = Cost::new(tokens[6].parse::<usize>()?, 0, 0);
-        let clay_robot_cost = Cost::new(tokens[12].parse::<usize>()?, 0, 0);
-        let obsidian_robot_cost = Cost::new(
-            tokens[18].parse::<usize>()?,
-            tokens[21].parse::<usize>()?,
-            0,
-        );
-        let geode_robot_cost = Cost::new(
-            tokens[27].parse::<usize>()?,
-            0,
-            tokens[30].parse::<usize>()?,
-        );
-        Ok(Self {
-            ore_robot_cost,
-            clay_robot_cost,
-            obsidian_robot_cost,
-            geode_robot_cost,
-        })
+        let (header, body) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("missing ':' in blueprint"))?;
+        let id = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            ["Blueprint", id] => id.parse()?,
+            _ => bail!("invalid blueprint header {header:?}"),
+        };
+
+        let mut costs = [None; 4];
+        for sentence in body.split('.').map(str::trim).filter(|s| !s.is_empty()) {
+            let words = sentence.split_whitespace().collect::<Vec<_>>();
+            let (robot, amounts) = match &words[..] {
+                ["Each", robot, "robot", "costs", amounts @ ..] => {
+                    (robot.parse::<Resource>()?, amounts)
+                }
+                _ => bail!("invalid robot cost {sentence:?}"),
+            };
+            let mut cost = Resources::default();
+            for amount in amounts.split(|&word| word == "and") {
+                match amount {
+                    [n, resource] => cost[resource.parse::<Resource>()?] += n.parse::<usize>()?,
+                    _ => bail!("invalid amount in {sentence:?}"),
+                }
+            }
+            ensure!(
+                costs[robot as usize].replace(cost).is_none(),
+                "duplicate cost for {robot:?} robot"
+            );
+        }
+
+        let mut blueprint = Self {
+            id,
+            costs: [Resources::default(); 4],
+        };
+        for (robot, cost) in Resource::ALL.into_iter().zip(costs) {
+            blueprint.costs[robot as usize] =
+                cost.ok_or_else(|| anyhow!("missing cost for {robot:?} robot"))?;
+        }
+        Ok(blueprint)
     }
 }
 
-#[derive(Clone, Debug)]
+/// Parses every blueprint in the input, which may span several lines each.
+fn parse_blueprints(input: &str) -> Result<Vec<Blueprint>> {
+    input
+        .split("Blueprint")
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| format!("Blueprint{s}").parse())
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct Env {
     minutes: usize,
-    ore: usize,
-    clay: usize,
-    obsidian: usize,
-    geode: usize,
-    ore_robots: usize,
-    clay_robots: usize,
-    obsidian_robots: usize,
-    geode_robots: usize,
+    stock: Resources,
+    robots: Resources,
 }
 
 impl Env {
     fn new() -> Self {
+        let mut robots = Resources::default();
+        robots[Resource::Ore] = 1;
         Self {
             minutes: 0,
-            ore: 0,
-            clay: 0,
-            obsidian: 0,
-            geode: 0,
-            ore_robots: 1,
-            clay_robots: 0,
-            obsidian_robots: 0,
-            geode_robots: 0,
+            stock: Resources::default(),
+            robots,
+        }
+    }
+}
+
+/// Times both searches on the first three blueprints at 32 minutes.
+fn bench(blueprints: &[Blueprint]) {
+    let init_env = Env::new();
+    for blueprint in blueprints.iter().take(3) {
+        let start = Instant::now();
+        let outcome = blueprint.simulate_dfs(&init_env, 32);
+        let elapsed = start.elapsed();
+        println!(
+            "Blueprint {}: {} geodes, {} nodes in {elapsed:?}",
+            blueprint.id, outcome.geodes, outcome.nodes
+        );
+
+        let start = Instant::now();
+        let geodes = blueprint.simulate_minutewise(&init_env, 32, 0);
+        println!(
+            "  minute by minute: {geodes} geodes in {:?}",
+            start.elapsed()
+        );
+    }
+}
+
+/// The results of one blueprint at both durations.
+#[derive(Clone, Debug)]
+struct Report {
+    id: usize,
+    /// The outcome after 24 minutes.
+    short: Outcome,
+    /// The outcome after 32 minutes.
+    long: Outcome,
+    elapsed: Duration,
+}
+
+impl Report {
+    fn new(blueprint: &Blueprint) -> Self {
+        let start = Instant::now();
+        let env = Env::new();
+        let short = blueprint.simulate_dfs(&env, 24);
+        let long = blueprint.simulate_dfs(&env, 32);
+        Self {
+            id: blueprint.id,
+            short,
+            long,
+            elapsed: start.elapsed(),
         }
     }
+
+    fn quality_level(&self) -> usize {
+        self.id * self.short.geodes
+    }
+}
+
+/// Evaluates the blueprints on all available cores, returning the reports in
+/// the order of the blueprints.
+fn evaluate(blueprints: &[Blueprint]) -> Vec<Report> {
+    let threads = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(blueprints.len());
+    let next = AtomicUsize::new(0);
+
+    let mut reports = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut reports = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(blueprint) = blueprints.get(i) else {
+                            break;
+                        };
+                        reports.push((i, Report::new(blueprint)));
+                    }
+                    reports
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    reports.sort_by_key(|&(i, _)| i);
+    reports.into_iter().map(|(_, report)| report).collect()
+}
+
+/// Prints the reports as a table.
+fn print_reports(reports: &[Report]) {
+    println!(
+        "{:>9} {:>6} {:>6} {:>7} {:>8} {:>8} {:>10}",
+        "blueprint", "24 min", "32 min", "quality", "nodes 24", "nodes 32", "time"
+    );
+    for report in reports {
+        println!(
+            "{:>9} {:>6} {:>6} {:>7} {:>8} {:>8} {:>10}",
+            report.id,
+            report.short.geodes,
+            report.long.geodes,
+            report.quality_level(),
+            report.short.nodes,
+            report.long.nodes,
+            format!("{:.2?}", report.elapsed)
+        );
+    }
 }
 
 fn main() -> Result<()> {
     let input = io::read_to_string(io::stdin())?;
-    let lines = input.lines().collect::<Vec<_>>();
-    let blueprints = lines
-        .iter()
-        .map(|&s| s.parse::<Blueprint>().unwrap())
-        .collect::<Vec<_>>();
+    let blueprints = parse_blueprints(&input)?;
 
-    let init_env = Env::new();
-    let mut total_quality_level = 0;
-    for (i, blueprint) in blueprints.iter().enumerate() {
-        let quality_level = blueprint.simulate_dfs(&init_env, 24, 0);
-        total_quality_level += quality_level * (i + 1);
+    // Optional flags: --bench | --report | --explain <id> [<minutes>]
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|arg| arg == "--bench") {
+        bench(&blueprints);
+        return Ok(());
     }
-    println!("Part 1: {total_quality_level}");
+    if let Some(i) = args.iter().position(|arg| arg == "--explain") {
+        let id = args
+            .get(i + 1)
+            .ok_or_else(|| anyhow!("missing blueprint id"))?
+            .parse::<usize>()?;
+        let duration = args.get(i + 2).map_or(Ok(24), |s| s.parse())?;
+        let blueprint = blueprints
+            .iter()
+            .find(|blueprint| blueprint.id == id)
+            .ok_or_else(|| anyhow!("no blueprint {id}"))?;
 
-    let mut product = 1;
-    for blueprint in blueprints.iter().take(3) {
-        let quality_level = blueprint.simulate_dfs(&init_env, 32, 0);
-        product *= quality_level;
+        let outcome = blueprint.simulate_dfs(&Env::new(), duration);
+        let (geodes, story) = blueprint.replay(&outcome.builds, duration)?;
+        ensure!(geodes == outcome.geodes, "the replay opens {geodes} geodes");
+        print!("{story}");
+        println!("Blueprint {id}: {geodes} geodes in {duration} minutes");
+        return Ok(());
     }
+
+    let reports = evaluate(&blueprints);
+    if args.iter().any(|arg| arg == "--report") {
+        print_reports(&reports);
+    }
+
+    let total_quality_level = reports.iter().map(Report::quality_level).sum::<usize>();
+    println!("Part 1: {total_quality_level}");
+
+    let product = reports
+        .iter()
+        .take(3)
+        .map(|report| report.long.geodes)
+        .product::<usize>();
     println!("Part 2: {product}");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let blueprints = parse_blueprints(
+            "\
+Blueprint 2:
+  Each ore robot costs 2 ore.
+  Each clay robot costs 3 ore.
+  Each geode robot costs 3 ore and 12 obsidian.
+  Each obsidian robot costs 3 ore and 8 clay.
+
+Blueprint 7: Each ore robot costs 4 ore. Each clay robot costs 2 ore. \
+Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.
+",
+        )
+        .unwrap();
+        assert_eq!(blueprints.len(), 2);
+        assert_eq!(blueprints[0].id, 2);
+        assert_eq!(
+            blueprints[0].cost(Resource::Geode),
+            &Resources([3, 0, 12, 0])
+        );
+        assert_eq!(
+            blueprints[0].cost(Resource::Obsidian),
+            &Resources([3, 8, 0, 0])
+        );
+        assert_eq!(blueprints[1].id, 7);
+        assert_eq!(blueprints[1].cost(Resource::Ore), &Resources([4, 0, 0, 0]));
+
+        assert!("Blueprint 1: Each ore robot costs 4 ore."
+            .parse::<Blueprint>()
+            .is_err());
+        assert!("Blueprint 1: Each gold robot costs 4 ore."
+            .parse::<Blueprint>()
+            .is_err());
+    }
+
+    #[test]
+    fn example() {
+        let blueprints = parse_blueprints(include_str!("../input/test.txt")).unwrap();
+        let env = Env::new();
+        let geodes = |duration| {
+            blueprints
+                .iter()
+                .map(|blueprint| blueprint.simulate_dfs(&env, duration).geodes)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(geodes(24), [9, 12]);
+        assert_eq!(geodes(32), [56, 62]);
+    }
+
+    #[test]
+    fn matches_minutewise() {
+        let blueprints = parse_blueprints(include_str!("../input/test.txt")).unwrap();
+        let env = Env::new();
+        for blueprint in &blueprints {
+            for duration in [10, 16, 20] {
+                assert_eq!(
+                    blueprint.simulate_dfs(&env, duration).geodes,
+                    blueprint.simulate_minutewise(&env, duration, 0)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reports() {
+        let blueprints = parse_blueprints(include_str!("../input/test.txt")).unwrap();
+        let reports = evaluate(&blueprints);
+        assert_eq!(
+            reports
+                .iter()
+                .map(|r| (r.id, r.short.geodes, r.long.geodes, r.quality_level()))
+                .collect::<Vec<_>>(),
+            [(1, 9, 56, 9), (2, 12, 62, 24)]
+        );
+        assert!(reports
+            .iter()
+            .all(|r| r.short.nodes > 0 && r.long.nodes > 0));
+    }
+
+    #[test]
+    fn replay() {
+        let blueprints = parse_blueprints(include_str!("../input/test.txt")).unwrap();
+        let build = |minute, robot| Build { minute, robot };
+
+        // the plan from the puzzle
+        let builds = [
+            build(3, Resource::Clay),
+            build(5, Resource::Clay),
+            build(7, Resource::Clay),
+            build(11, Resource::Obsidian),
+            build(12, Resource::Clay),
+            build(15, Resource::Obsidian),
+            build(18, Resource::Geode),
+            build(21, Resource::Geode),
+        ];
+        let (geodes, story) = blueprints[0].replay(&builds, 24).unwrap();
+        assert_eq!(geodes, 9);
+        assert!(story.starts_with(
+            "\
+== Minute 1 ==
+1 ore-collecting robot collects 1 ore; you now have 1 ore.
+
+== Minute 2 ==
+1 ore-collecting robot collects 1 ore; you now have 2 ore.
+
+== Minute 3 ==
+Spend 2 ore to start building a clay-collecting robot.
+1 ore-collecting robot collects 1 ore; you now have 1 ore.
+The new clay-collecting robot is ready; you now have 1 of them.
+"
+        ));
+        assert!(story.contains(
+            "\
+== Minute 21 ==
+Spend 2 ore and 7 obsidian to start building a geode-cracking robot.
+1 ore-collecting robot collects 1 ore; you now have 3 ore.
+4 clay-collecting robots collect 4 clay; you now have 29 clay.
+2 obsidian-collecting robots collect 2 obsidian; you now have 2 obsidian.
+1 geode-cracking robot cracks 1 geode; you now have 3 open geodes.
+The new geode-cracking robot is ready; you now have 2 of them.
+"
+        ));
+
+        assert!(blueprints[0]
+            .replay(&[build(1, Resource::Clay)], 24)
+            .is_err());
+
+        for blueprint in &blueprints {
+            for duration in [24, 32] {
+                let outcome = blueprint.simulate_dfs(&Env::new(), duration);
+                let (geodes, _) = blueprint.replay(&outcome.builds, duration).unwrap();
+                assert_eq!(geodes, outcome.geodes);
+            }
+        }
+    }
+}
@@ -1,88 +1,79 @@
-#![allow(clippy::cast_sign_loss)]
-use std::io;
+#![allow(
+    clippy::cast_sign_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap
+)]
+use std::{env, io};
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
+use circular::CircularList;
 
 #[derive(Debug)]
 struct Text {
-    text: Vec<isize>,
-    forward: Vec<usize>,
-    reverse: Vec<usize>,
+    numbers: CircularList<isize>,
 }
 
 impl Text {
     fn from_vec(text: Vec<isize>) -> Self {
-        let forward = (0..text.len()).collect();
-        let reverse = (0..text.len()).collect();
         Self {
-            text,
-            forward,
-            reverse,
+            numbers: CircularList::from_vec(text),
         }
     }
 
-    fn text(&self) -> Vec<isize> {
-        let mut decrypted = vec![0; self.text.len()];
-        for (i, &c) in self.text.iter().enumerate() {
-            decrypted[self.forward[i]] = c;
-        }
-        decrypted
+    fn coordinates(&self) -> isize {
+        let zero = self.numbers.find(|&c| c == 0).unwrap();
+        [1000, 2000, 3000]
+            .into_iter()
+            .map(|offset| self.numbers.get_from(zero, offset))
+            .sum()
     }
 
-    fn coordinates(&self) -> isize {
-        let text = self.text();
-        let pos = text.iter().position(|&c| c == 0).unwrap();
-        let a = text[(pos + 1000) % text.len()];
-        let b = text[(pos + 2000) % text.len()];
-        let c = text[(pos + 3000) % text.len()];
-        a + b + c
-    }
-
-    fn move_left(&mut self, i: usize) {
-        let pos = self.forward[i];
-        if pos > 1 {
-            self.forward.swap(i, self.reverse[pos - 1]);
-            self.reverse.swap(pos, pos - 1);
-        } else {
-            let dst = self.reverse.len() - 2 + pos;
-            for cur in pos..dst {
-                self.forward.swap(self.reverse[cur], self.reverse[cur + 1]);
-                self.reverse.swap(cur, cur + 1);
-            }
+    fn mix(&mut self) {
+        for handle in self.numbers.handles() {
+            let offset = *self.numbers.get(handle);
+            self.numbers.move_by(handle, offset);
         }
     }
 
-    fn move_right(&mut self, i: usize) {
-        let pos = self.forward[i];
-        if pos < self.forward.len() - 1 {
-            self.forward.swap(i, self.reverse[pos + 1]);
-            self.reverse.swap(pos, pos + 1);
-        } else {
-            for cur in (2..self.reverse.len()).rev() {
-                self.forward.swap(self.reverse[cur], self.reverse[cur - 1]);
-                self.reverse.swap(cur, cur - 1);
-            }
-        }
+    fn text(&self) -> Vec<isize> {
+        self.numbers.iter().copied().collect()
     }
 
-    fn mix(&mut self) {
-        let original = self.text.clone();
-        for (i, &x) in original.iter().enumerate() {
-            let n = x.unsigned_abs() % (original.len() - 1);
-            for _ in 0..n {
-                if x > 0 {
-                    self.move_right(i);
-                } else {
-                    self.move_left(i);
-                }
-            }
+    /// Undoes a mix by moving the numbers back in the reverse of their
+    /// original order, which the handles keep, then puts the first number of
+    /// the original text back at the front.
+    fn unmix(&mut self) {
+        let handles = self.numbers.handles().collect::<Vec<_>>();
+        for &handle in handles.iter().rev() {
+            let offset = *self.numbers.get(handle);
+            self.numbers.move_by(handle, -offset);
+        }
+        // a number moved back to the front ends up at the back instead
+        if let Some(&first) = handles.first() {
+            self.numbers.rotate(self.numbers.position(first) as isize);
         }
     }
 }
 
+/// Checks that unmixing the text as many times as it was mixed gives back
+/// the original.
+fn verify(text: &mut Text, original: &[isize], rounds: usize) -> Result<()> {
+    for _ in 0..rounds {
+        text.unmix();
+    }
+    ensure!(
+        text.text() == original,
+        "unmixing {rounds} time(s) does not give back the original text"
+    );
+    Ok(())
+}
+
 fn main() -> Result<()> {
     const DECRYPTION_KEY: isize = 811_589_153;
 
+    // Optional flag: --verify, unmixing after each part
+    let check = env::args().skip(1).any(|arg| arg == "--verify");
+
     let input = io::read_to_string(io::stdin())?;
     let lines = input.lines().collect::<Vec<_>>();
     let original = lines
@@ -92,16 +83,229 @@ fn main() -> Result<()> {
     let mut text = Text::from_vec(original.clone());
     text.mix();
     println!("Part 1: {}", text.coordinates());
+    if check {
+        verify(&mut text, &original, 1)?;
+    }
 
     let original = original
         .iter()
         .map(|&x| x * DECRYPTION_KEY)
         .collect::<Vec<_>>();
-    let mut text = Text::from_vec(original);
+    let mut text = Text::from_vec(original.clone());
     for _ in 0..10 {
         text.mix();
     }
     println!("Part 2: {}", text.coordinates());
+    if check {
+        verify(&mut text, &original, 10)?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// A xorshift generator, good enough to pick test inputs.
+    struct Random(u64);
+
+    impl Random {
+        fn new() -> Self {
+            Self(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Returns a number in `-max..=max`.
+        fn signed(&mut self, max: isize) -> isize {
+            (self.next() % (2 * max as u64 + 1)) as isize - max
+        }
+
+        /// Returns numbers picked to hit the edge cases of mixing: duplicates,
+        /// zeros, multiples of `len - 1`, numbers next to them and numbers
+        /// wrapping around many times.
+        fn numbers(&mut self, len: usize) -> Vec<isize> {
+            let cycle = len.saturating_sub(1).max(1) as isize;
+            (0..len)
+                .map(|_| match self.next() % 4 {
+                    0 => self.signed(3),
+                    1 => self.signed(3) * cycle,
+                    2 => self.signed(3) * cycle + self.signed(1),
+                    _ => self.signed(1_000_000_000_000),
+                })
+                .collect()
+        }
+    }
+
+    /// Mixes by rotating a deque so that the number to move is at the front,
+    /// returning the numbers starting from the first one of `numbers`.
+    fn mix_deque(numbers: &[isize], rounds: usize) -> Vec<isize> {
+        let len = numbers.len();
+        let mut deque = numbers.iter().copied().enumerate().collect::<VecDeque<_>>();
+        for _ in 0..rounds {
+            for i in 0..len {
+                let pos = deque.iter().position(|&(j, _)| j == i).unwrap();
+                deque.rotate_left(pos);
+                let (j, x) = deque.pop_front().unwrap();
+                if !deque.is_empty() {
+                    deque.rotate_left(x.rem_euclid(len as isize - 1) as usize);
+                }
+                deque.push_front((j, x));
+            }
+        }
+        let pos = deque.iter().position(|&(j, _)| j == 0).unwrap_or(0);
+        deque.rotate_left(pos);
+        deque.into_iter().map(|(_, x)| x).collect()
+    }
+
+    /// Returns the numbers starting from the first one of the original text.
+    fn from_first(text: &Text) -> Vec<isize> {
+        match text.numbers.handles().next() {
+            Some(first) => text.numbers.iter_from(first).copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The original mixer, moving numbers one swap at a time.
+    struct SwapText {
+        text: Vec<isize>,
+        forward: Vec<usize>,
+        reverse: Vec<usize>,
+    }
+
+    impl SwapText {
+        fn from_vec(text: Vec<isize>) -> Self {
+            let forward = (0..text.len()).collect();
+            let reverse = (0..text.len()).collect();
+            Self {
+                text,
+                forward,
+                reverse,
+            }
+        }
+
+        fn text(&self) -> Vec<isize> {
+            let mut decrypted = vec![0; self.text.len()];
+            for (i, &c) in self.text.iter().enumerate() {
+                decrypted[self.forward[i]] = c;
+            }
+            decrypted
+        }
+
+        fn move_left(&mut self, i: usize) {
+            let pos = self.forward[i];
+            if pos > 1 {
+                self.forward.swap(i, self.reverse[pos - 1]);
+                self.reverse.swap(pos, pos - 1);
+            } else {
+                let dst = self.reverse.len() - 2 + pos;
+                for cur in pos..dst {
+                    self.forward.swap(self.reverse[cur], self.reverse[cur + 1]);
+                    self.reverse.swap(cur, cur + 1);
+                }
+            }
+        }
+
+        fn move_right(&mut self, i: usize) {
+            let pos = self.forward[i];
+            if pos < self.forward.len() - 1 {
+                self.forward.swap(i, self.reverse[pos + 1]);
+                self.reverse.swap(pos, pos + 1);
+            } else {
+                for cur in (2..self.reverse.len()).rev() {
+                    self.forward.swap(self.reverse[cur], self.reverse[cur - 1]);
+                    self.reverse.swap(cur, cur - 1);
+                }
+            }
+        }
+
+        fn mix(&mut self) {
+            let original = self.text.clone();
+            for (i, &x) in original.iter().enumerate() {
+                let n = x.unsigned_abs() % (original.len() - 1);
+                for _ in 0..n {
+                    if x > 0 {
+                        self.move_right(i);
+                    } else {
+                        self.move_left(i);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn example() {
+        let original = [1, 2, -3, 3, -2, 0, 4];
+        let mut text = Text::from_vec(original.to_vec());
+        text.mix();
+        assert_eq!(text.text(), [1, 2, -3, 4, 0, 3, -2]);
+        assert_eq!(text.coordinates(), 3);
+
+        let mut text = Text::from_vec(original.iter().map(|&x| x * 811_589_153).collect());
+        for _ in 0..10 {
+            text.mix();
+        }
+        assert_eq!(text.coordinates(), 1_623_178_306);
+    }
+
+    #[test]
+    fn matches_swaps() {
+        let mut random = Random::new();
+        for len in 2..60 {
+            let original = (0..len).map(|_| random.signed(100)).collect::<Vec<_>>();
+            let mut text = Text::from_vec(original.clone());
+            let mut swaps = SwapText::from_vec(original);
+            for _ in 0..3 {
+                text.mix();
+                swaps.mix();
+                assert_eq!(text.text(), swaps.text());
+            }
+        }
+    }
+
+    #[test]
+    fn matches_deque() {
+        let mut random = Random::new();
+        for len in 1..60 {
+            let original = random.numbers(len);
+            let mut text = Text::from_vec(original.clone());
+            for rounds in 1..=3 {
+                text.mix();
+                assert_eq!(from_first(&text), mix_deque(&original, rounds));
+            }
+        }
+    }
+
+    #[test]
+    fn unmix() {
+        let original = vec![1, 2, -3, 3, -2, 0, 4];
+        let mut text = Text::from_vec(original.clone());
+        text.mix();
+        text.unmix();
+        assert_eq!(text.text(), original);
+
+        let mut random = Random::new();
+        for len in 0..60 {
+            let original = random.numbers(len);
+            for rounds in [1, 10] {
+                let mut text = Text::from_vec(original.clone());
+                for _ in 0..rounds {
+                    text.mix();
+                }
+                for _ in 0..rounds {
+                    text.unmix();
+                }
+                assert_eq!(text.text(), original);
+            }
+        }
+    }
+}
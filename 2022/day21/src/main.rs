@@ -1,8 +1,22 @@
-use std::{collections::HashMap, io, str::FromStr};
+use std::{collections::HashMap, env, fmt, io, str::FromStr};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Result};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
 
-#[derive(Debug, PartialEq, Eq)]
+/// The name of the monkey that is really the human.
+const HUMAN: &str = "humn";
+
+/// The most operations an expression may nest, so that formatting and
+/// dropping it, which recurse, stay well within the stack.
+const MAX_DEPTH: usize = 1000;
+
+/// The most numbers, humans and operations an expression may hold, as a
+/// monkey waited for twice is written out twice.
+const MAX_NODES: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Op {
     Add,
     Sub,
@@ -10,125 +24,830 @@ enum Op {
     Div,
 }
 
-impl From<char> for Op {
-    fn from(c: char) -> Self {
-        match c {
-            '+' => Op::Add,
-            '-' => Op::Sub,
-            '*' => Op::Mul,
-            '/' => Op::Div,
-            _ => unreachable!(),
+impl FromStr for Op {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "+" => Ok(Op::Add),
+            "-" => Ok(Op::Sub),
+            "*" => Ok(Op::Mul),
+            "/" => Ok(Op::Div),
+            _ => Err(anyhow!("unknown operation {s:?}")),
+        }
+    }
+}
+
+impl Op {
+    /// Returns how tightly the operation binds, higher binding tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+        };
+        write!(f, "{c}")
+    }
+}
+
+/// A monkey's job with the monkeys it waits for replaced by their own jobs,
+/// down to the human. Expressions nest at most [`MAX_DEPTH`] operations and
+/// hold at most [`MAX_NODES`] nodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr<N> {
+    Number(N),
+    Human,
+    Operation(Op, Box<Expr<N>>, Box<Expr<N>>),
+}
+
+impl<N: fmt::Display> Expr<N> {
+    /// Writes an operand of `op`, in parentheses if it is an operation. In
+    /// alternate mode, parentheses are left out where precedence makes them
+    /// redundant.
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, op: Op, right: bool) -> fmt::Result {
+        let Expr::Operation(inner, ..) = self else {
+            return write!(f, "{self}");
+        };
+        let minimal = f.alternate();
+        let redundant = inner.precedence() > op.precedence()
+            || inner.precedence() == op.precedence()
+                && (!right || op == Op::Add || op == Op::Mul && *inner == Op::Mul);
+        if minimal && redundant {
+            write!(f, "{self:#}")
+        } else if minimal {
+            write!(f, "({self:#})")
+        } else {
+            write!(f, "({self})")
+        }
+    }
+}
+
+/// Writes the expression with every operation in parentheses, or with `{:#}`
+/// only those that change the order of evaluation.
+impl<N: fmt::Display> fmt::Display for Expr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{n}"),
+            Expr::Human => write!(f, "{HUMAN}"),
+            Expr::Operation(op, lhs, rhs) => {
+                lhs.fmt_operand(f, *op, false)?;
+                write!(f, " {op} ")?;
+                rhs.fmt_operand(f, *op, true)
+            }
+        }
+    }
+}
+
+/// A kind of number to evaluate the monkeys' jobs with.
+trait Number: Clone + PartialEq + fmt::Display {
+    fn from_isize(n: isize) -> Self;
+
+    /// Returns the result of the operation, or why there is none.
+    fn apply(op: Op, lhs: &Self, rhs: &Self) -> Result<Self, &'static str>;
+
+    /// Returns the number if it is an integer that fits an `isize`.
+    fn integer(&self) -> Option<isize>;
+}
+
+/// Checked integers, failing on overflow and on divisions with a remainder.
+impl Number for i64 {
+    fn from_isize(n: isize) -> Self {
+        n as i64
+    }
+
+    fn apply(op: Op, lhs: &Self, rhs: &Self) -> Result<Self, &'static str> {
+        let result = match op {
+            Op::Add => lhs.checked_add(*rhs),
+            Op::Sub => lhs.checked_sub(*rhs),
+            Op::Mul => lhs.checked_mul(*rhs),
+            Op::Div if *rhs == 0 => return Err("divides by zero"),
+            Op::Div if lhs.checked_rem(*rhs).is_some_and(|rem| rem != 0) => {
+                return Err("divides inexactly")
+            }
+            Op::Div => lhs.checked_div(*rhs),
+        };
+        result.ok_or("overflows")
+    }
+
+    fn integer(&self) -> Option<isize> {
+        isize::try_from(*self).ok()
+    }
+}
+
+/// Wide integers, dividing with truncation like the monkeys do and failing
+/// only on overflow.
+impl Number for i128 {
+    fn from_isize(n: isize) -> Self {
+        n as i128
+    }
+
+    fn apply(op: Op, lhs: &Self, rhs: &Self) -> Result<Self, &'static str> {
+        let result = match op {
+            Op::Add => lhs.checked_add(*rhs),
+            Op::Sub => lhs.checked_sub(*rhs),
+            Op::Mul => lhs.checked_mul(*rhs),
+            Op::Div if *rhs == 0 => return Err("divides by zero"),
+            Op::Div => lhs.checked_div(*rhs),
+        };
+        result.ok_or("overflows")
+    }
+
+    fn integer(&self) -> Option<isize> {
+        isize::try_from(*self).ok()
+    }
+}
+
+/// Exact fractions of any size.
+impl Number for BigRational {
+    fn from_isize(n: isize) -> Self {
+        BigRational::from_integer(BigInt::from(n))
+    }
+
+    fn apply(op: Op, lhs: &Self, rhs: &Self) -> Result<Self, &'static str> {
+        Ok(match op {
+            Op::Add => lhs + rhs,
+            Op::Sub => lhs - rhs,
+            Op::Mul => lhs * rhs,
+            Op::Div if rhs.is_zero() => return Err("divides by zero"),
+            Op::Div => lhs / rhs,
+        })
+    }
+
+    fn integer(&self) -> Option<isize> {
+        self.is_integer().then(|| self.to_integer().to_isize())?
+    }
+}
+
+/// The kinds of numbers to choose from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    Checked,
+    Wide,
+    Rational,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "checked" => Ok(Backend::Checked),
+            "i128" => Ok(Backend::Wide),
+            "rational" => Ok(Backend::Rational),
+            _ => Err(anyhow!(
+                "unknown numbers {s:?}, expected checked, i128 or rational"
+            )),
         }
     }
 }
 
+/// What the human yells when evaluating the monkeys.
+enum Yell<N> {
+    /// The number given in the input.
+    Given,
+    /// Nothing, so that the monkeys depending on the human have no number.
+    Unknown,
+    Number(N),
+}
+
+/// A monkey's job, waiting for other monkeys referred to by `T`.
 #[derive(Debug)]
-enum Job {
+enum Job<T> {
     Number(isize),
-    Operation((Op, String, String)),
+    Operation(Op, T, T),
 }
 
 #[derive(Debug)]
 struct Monkey {
     name: String,
-    job: Job,
+    job: Job<String>,
 }
 
 impl FromStr for Monkey {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        let mut parts = s.split_whitespace();
-        let name = parts.next().unwrap().chars().take(4).collect();
-        let operand = parts.next().unwrap();
-        if let Some(operator) = parts.next() {
-            let op = operator.chars().next().unwrap().into();
-            let lhs = operand;
-            let rhs = parts.next().unwrap();
-            Ok(Monkey {
-                name,
-                job: Job::Operation((op, lhs.to_string(), rhs.to_string())),
-            })
-        } else {
-            let number = operand.parse()?;
-            Ok(Monkey {
-                name,
-                job: Job::Number(number),
-            })
-        }
+        let (name, job) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("missing ':' in {s:?}"))?;
+        let is_name = |name: &str| !name.is_empty() && name.chars().all(char::is_alphanumeric);
+        ensure!(is_name(name), "invalid monkey name {name:?}");
+
+        let job = match job.split_whitespace().collect::<Vec<_>>()[..] {
+            [number] => Job::Number(number.parse()?),
+            [lhs, op, rhs] => {
+                ensure!(is_name(lhs), "invalid monkey name {lhs:?}");
+                ensure!(is_name(rhs), "invalid monkey name {rhs:?}");
+                Job::Operation(op.parse()?, lhs.to_string(), rhs.to_string())
+            }
+            _ => bail!("invalid job {job:?}"),
+        };
+        Ok(Monkey {
+            name: name.to_string(),
+            job,
+        })
     }
 }
 
+/// The monkeys, referring to each other by their index in `names`.
 struct Monkeys {
-    monkeys: HashMap<String, Job>,
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    jobs: Vec<Job<usize>>,
+    /// The monkeys ordered so that each comes after those it waits for.
+    order: Vec<usize>,
+    root: usize,
+    human: Option<usize>,
 }
 
 impl Monkeys {
-    fn yell(&self) -> isize {
-        self.eval("root")
-    }
-
-    fn eval(&self, name: &str) -> isize {
-        match self.monkeys.get(name).unwrap() {
-            Job::Number(n) => *n,
-            Job::Operation((op, lhs, rhs)) => {
-                let lhs = self.eval(lhs);
-                let rhs = self.eval(rhs);
-                match op {
-                    Op::Add => lhs + rhs,
-                    Op::Sub => lhs - rhs,
-                    Op::Mul => lhs * rhs,
-                    Op::Div => lhs / rhs,
+    /// Links the monkeys together, checking that every monkey they wait for
+    /// exists and that no monkey ends up waiting for itself.
+    fn new(monkeys: Vec<Monkey>) -> Result<Self> {
+        let mut names = Vec::with_capacity(monkeys.len());
+        let mut index = HashMap::with_capacity(monkeys.len());
+        for (i, monkey) in monkeys.iter().enumerate() {
+            ensure!(
+                index.insert(monkey.name.clone(), i).is_none(),
+                "monkey {} appears twice",
+                monkey.name
+            );
+            names.push(monkey.name.clone());
+        }
+        let find = |name: &str, by: &str| {
+            index
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("monkey {by} waits for unknown monkey {name}"))
+        };
+        let jobs = monkeys
+            .into_iter()
+            .map(|monkey| match monkey.job {
+                Job::Number(n) => Ok(Job::Number(n)),
+                Job::Operation(op, lhs, rhs) => Ok(Job::Operation(
+                    op,
+                    find(&lhs, &monkey.name)?,
+                    find(&rhs, &monkey.name)?,
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let root = index
+            .get("root")
+            .copied()
+            .ok_or_else(|| anyhow!("no root monkey"))?;
+        let human = index.get(HUMAN).copied();
+
+        let mut monkeys = Self {
+            names,
+            index,
+            jobs,
+            order: Vec::new(),
+            root,
+            human,
+        };
+        monkeys.order = monkeys.topological_order()?;
+        Ok(monkeys)
+    }
+
+    /// Orders the monkeys so that each comes after those it waits for, or
+    /// fails with the monkeys waiting for each other in a cycle.
+    fn topological_order(&self) -> Result<Vec<usize>> {
+        let mut waiting = vec![0; self.jobs.len()];
+        let mut listeners = vec![Vec::new(); self.jobs.len()];
+        for (i, job) in self.jobs.iter().enumerate() {
+            if let Job::Operation(_, lhs, rhs) = *job {
+                waiting[i] = 2;
+                listeners[lhs].push(i);
+                listeners[rhs].push(i);
+            }
+        }
+
+        let mut order = (0..self.jobs.len())
+            .filter(|&i| waiting[i] == 0)
+            .collect::<Vec<_>>();
+        let mut next = 0;
+        while let Some(&i) = order.get(next) {
+            next += 1;
+            for &listener in &listeners[i] {
+                waiting[listener] -= 1;
+                if waiting[listener] == 0 {
+                    order.push(listener);
                 }
             }
         }
+        if order.len() == self.jobs.len() {
+            return Ok(order);
+        }
+
+        // follow monkeys still waiting until one comes around again
+        let mut i = (0..self.jobs.len()).find(|&i| waiting[i] > 0).unwrap();
+        let mut seen = vec![false; self.jobs.len()];
+        while !seen[i] {
+            seen[i] = true;
+            let Job::Operation(_, lhs, rhs) = self.jobs[i] else {
+                unreachable!("only operations wait");
+            };
+            i = if waiting[lhs] > 0 { lhs } else { rhs };
+        }
+        let mut cycle = vec![self.names[i].as_str()];
+        let start = i;
+        loop {
+            let Job::Operation(_, lhs, rhs) = self.jobs[i] else {
+                unreachable!("only operations wait");
+            };
+            i = if waiting[lhs] > 0 { lhs } else { rhs };
+            cycle.push(&self.names[i]);
+            if i == start {
+                break;
+            }
+        }
+        bail!("monkeys wait for each other: {}", cycle.join(" -> "))
     }
 
-    fn cmp(&mut self, lhs: &str, rhs: &str, n: isize) -> std::cmp::Ordering {
-        self.monkeys.insert("humn".to_string(), Job::Number(n));
-        let l_num = self.eval(lhs);
-        let r_num = self.eval(rhs);
-        l_num.cmp(&r_num)
+    fn index(&self, name: &str) -> Result<usize> {
+        self.index
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("no monkey {name}"))
+    }
+
+    /// Returns the number each monkey yells, or `None` for the monkeys that
+    /// depend on the human if what the human yells is unknown.
+    fn evaluate<N: Number>(&self, human: &Yell<N>) -> Result<Vec<Option<N>>> {
+        let mut values: Vec<Option<N>> = vec![None; self.jobs.len()];
+        for &i in &self.order {
+            values[i] = match (&self.jobs[i], human) {
+                (_, Yell::Unknown) if Some(i) == self.human => None,
+                (_, Yell::Number(n)) if Some(i) == self.human => Some(n.clone()),
+                (&Job::Number(n), _) => Some(N::from_isize(n)),
+                (&Job::Operation(op, lhs, rhs), _) => {
+                    let (Some(lhs), Some(rhs)) = (&values[lhs], &values[rhs]) else {
+                        continue;
+                    };
+                    let value = N::apply(op, lhs, rhs)
+                        .map_err(|reason| anyhow!("monkey {} {reason}", self.names[i]))?;
+                    Some(value)
+                }
+            };
+        }
+        Ok(values)
+    }
+
+    fn yell<N: Number>(&self) -> Result<N> {
+        Ok(self.evaluate(&Yell::Given)?[self.root].clone().unwrap())
+    }
+
+    /// Returns the job of the monkey as an expression of the human's number,
+    /// folding everything that does not depend on it into numbers.
+    fn expr<N: Number>(&self, name: &str) -> Result<Expr<N>> {
+        let constants = self.evaluate(&Yell::Unknown)?;
+        self.fold(self.index(name)?, &constants)
+    }
+
+    /// Builds the expression of monkey `i` bottom-up in `order`, failing if
+    /// it nests deeper than [`MAX_DEPTH`] or holds more than [`MAX_NODES`].
+    fn fold<N: Number>(&self, i: usize, constants: &[Option<N>]) -> Result<Expr<N>> {
+        // count how many times each monkey appears in the expression, so that
+        // its own expression is only copied for all but the last use
+        let mut uses = vec![0; self.jobs.len()];
+        uses[i] = 1;
+        let mut stack = vec![i];
+        while let Some(j) = stack.pop() {
+            if let (None, &Job::Operation(_, lhs, rhs)) = (&constants[j], &self.jobs[j]) {
+                for k in [lhs, rhs] {
+                    if uses[k] == 0 {
+                        stack.push(k);
+                    }
+                    uses[k] += 1;
+                }
+            }
+        }
+
+        // every expression built, copies included, ends up in the one of
+        // monkey `i`, so check their depth and size before building any
+        let mut shapes = vec![(0, 1); self.jobs.len()];
+        for &j in &self.order {
+            if uses[j] == 0 {
+                continue;
+            }
+            if let (None, &Job::Operation(_, lhs, rhs)) = (&constants[j], &self.jobs[j]) {
+                let depth = shapes[lhs].0.max(shapes[rhs].0) + 1;
+                let nodes = shapes[lhs].1 + shapes[rhs].1 + 1;
+                ensure!(
+                    depth <= MAX_DEPTH,
+                    "the expression of monkey {} nests more than {MAX_DEPTH} operations",
+                    self.names[j]
+                );
+                ensure!(
+                    nodes <= MAX_NODES,
+                    "the expression of monkey {} holds more than {MAX_NODES} nodes",
+                    self.names[j]
+                );
+                shapes[j] = (depth, nodes);
+            }
+        }
+
+        // the expression of each monkey, until used up
+        let mut slots: Vec<Option<Expr<N>>> = vec![None; self.jobs.len()];
+        for &j in &self.order {
+            if uses[j] == 0 {
+                continue;
+            }
+            let expr = match (&constants[j], &self.jobs[j]) {
+                (Some(n), _) => Expr::Number(n.clone()),
+                (None, Job::Number(_)) => Expr::Human,
+                (None, &Job::Operation(op, lhs, rhs)) => {
+                    let [lhs, rhs] = [lhs, rhs].map(|k| {
+                        uses[k] -= 1;
+                        if uses[k] == 0 {
+                            slots[k].take()
+                        } else {
+                            slots[k].clone()
+                        }
+                        .unwrap()
+                    });
+                    Expr::Operation(op, Box::new(lhs), Box::new(rhs))
+                }
+            };
+            slots[j] = Some(expr);
+        }
+        Ok(slots[i].take().unwrap())
+    }
+
+    /// Returns the monkeys on both sides of `root`.
+    fn sides(&self) -> Result<(usize, usize)> {
+        match self.jobs[self.root] {
+            Job::Operation(_, lhs, rhs) => Ok((lhs, rhs)),
+            Job::Number(_) => bail!("root does not compare two monkeys"),
+        }
+    }
+
+    /// Returns the expressions on both sides of `root`.
+    fn equation<N: Number>(&self) -> Result<(Expr<N>, Expr<N>)> {
+        let (lhs, rhs) = self.sides()?;
+        let constants = self.evaluate(&Yell::Unknown)?;
+        Ok((self.fold(lhs, &constants)?, self.fold(rhs, &constants)?))
+    }
+
+    /// Finds the number the human must yell for both sides of `root` to be
+    /// equal, undoing the operations between `root` and the human one by one.
+    fn solve<N: Number>(&self) -> Result<isize> {
+        let (lhs, rhs) = self.sides()?;
+        let constants = self.evaluate::<N>(&Yell::Unknown)?;
+        let (mut i, mut target) = match (&constants[lhs], &constants[rhs]) {
+            (None, Some(target)) => (lhs, target.clone()),
+            (Some(target), None) => (rhs, target.clone()),
+            (None, None) => bail!("{HUMAN} appears on both sides of root"),
+            (Some(_), Some(_)) => bail!("{HUMAN} does not appear under root"),
+        };
+
+        while Some(i) != self.human {
+            let Job::Operation(op, lhs, rhs) = self.jobs[i] else {
+                unreachable!("only operations depend on {HUMAN}");
+            };
+            let undo = |op, a: &N, b: &N| {
+                N::apply(op, a, b).map_err(|reason| {
+                    anyhow!("undoing monkey {}: {a} {op} {b} {reason}", self.names[i])
+                })
+            };
+            (i, target) = match (&constants[lhs], &constants[rhs]) {
+                (None, None) => bail!("{HUMAN} appears on both sides of {}", self.names[i]),
+                // target = human op k
+                (None, Some(k)) => {
+                    let inverse = match op {
+                        Op::Add => Op::Sub,
+                        Op::Sub => Op::Add,
+                        Op::Mul => Op::Div,
+                        Op::Div => Op::Mul,
+                    };
+                    (lhs, undo(inverse, &target, k)?)
+                }
+                // target = k op human
+                (Some(k), _) => match op {
+                    Op::Add => (rhs, undo(Op::Sub, &target, k)?),
+                    Op::Sub => (rhs, undo(Op::Sub, k, &target)?),
+                    Op::Mul => (rhs, undo(Op::Div, &target, k)?),
+                    Op::Div => (rhs, undo(Op::Div, k, &target)?),
+                },
+            };
+        }
+
+        let answer = target
+            .integer()
+            .ok_or_else(|| anyhow!("{HUMAN} must yell {target}, not an integer"))?;
+        // with truncating division the operations may not undo exactly
+        let values = self.evaluate(&Yell::Number(N::from_isize(answer)))?;
+        ensure!(
+            values[lhs] == values[rhs],
+            "{HUMAN} yelling {answer} does not balance root"
+        );
+        Ok(answer)
+    }
+}
+
+impl FromStr for Monkeys {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let monkeys = s.lines().map(str::parse).collect::<Result<Vec<Monkey>>>()?;
+        Self::new(monkeys)
     }
 }
 
+/// Runs both parts with the given kind of numbers.
+fn run<N: Number>(monkeys: &Monkeys, args: &[String]) -> Result<()> {
+    // Optional flag: --formula [<monkey>], showing root's equation by default
+    if let Some(i) = args.iter().position(|arg| arg == "--formula") {
+        if let Some(name) = args.get(i + 1).filter(|arg| !arg.starts_with("--")) {
+            println!("{:#}", monkeys.expr::<N>(name)?);
+        } else {
+            let (lhs, rhs) = monkeys.equation::<N>()?;
+            println!("{lhs:#} = {rhs:#}");
+        }
+        return Ok(());
+    }
+
+    let answer = monkeys.yell::<N>()?;
+    println!("Part 1: {answer}");
+
+    let answer = monkeys.solve::<N>()?;
+    println!("Part 2: {answer}");
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let input = io::read_to_string(io::stdin())?;
-    let lines = input.lines().collect::<Vec<_>>();
-    let jobs = lines
-        .iter()
-        .map(|&s| s.parse::<Monkey>().unwrap())
-        .map(|m| (m.name, m.job))
-        .collect::<HashMap<_, _>>();
-    let mut monkeys = Monkeys { monkeys: jobs };
-
-    let answer = monkeys.yell();
-    println!("Part 1: {answer}");
+    let monkeys = input.parse::<Monkeys>()?;
 
-    let (lhs, rhs) = match monkeys.monkeys.get_mut("root").unwrap() {
-        Job::Operation((_, lhs, rhs)) => (lhs.clone(), rhs.clone()),
-        Job::Number(_) => unreachable!(),
+    // Optional flag: --numbers checked|i128|rational
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let backend = match args.iter().position(|arg| arg == "--numbers") {
+        Some(i) => args
+            .get(i + 1)
+            .ok_or_else(|| anyhow!("missing kind of numbers"))?
+            .parse()?,
+        None => Backend::Checked,
     };
-    let mut min = 0;
-    let mut max = 10_000_000_000_000;
-    let mut answer;
-
-    loop {
-        let middle = (min + max) / 2;
-        match monkeys.cmp(&lhs, &rhs, middle) {
-            std::cmp::Ordering::Less => max = middle + 1,
-            std::cmp::Ordering::Greater => min = middle - 1,
-            std::cmp::Ordering::Equal => {
-                answer = middle;
-                break;
-            }
+    match backend {
+        Backend::Checked => run::<i64>(&monkeys, &args),
+        Backend::Wide => run::<i128>(&monkeys, &args),
+        Backend::Rational => run::<BigRational>(&monkeys, &args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::*;
+
+    #[test]
+    fn example() {
+        let monkeys = include_str!("../input/test.txt")
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(monkeys.yell::<i64>().unwrap(), 152);
+        assert_eq!(monkeys.solve::<i64>().unwrap(), 301);
+    }
+
+    #[test]
+    fn formula() {
+        let monkeys = include_str!("../input/test.txt")
+            .parse::<Monkeys>()
+            .unwrap();
+        let (lhs, rhs) = monkeys.equation::<i64>().unwrap();
+        assert_eq!(format!("{lhs} = {rhs}"), "(4 + (2 * (humn - 3))) / 4 = 150");
+        assert_eq!(
+            format!("{lhs:#} = {rhs:#}"),
+            "(4 + 2 * (humn - 3)) / 4 = 150"
+        );
+        assert_eq!(monkeys.expr::<i64>("sjmn").unwrap().to_string(), "150");
+        assert_eq!(monkeys.expr::<i64>("humn").unwrap().to_string(), "humn");
+
+        let monkeys = "root: aaaa + bbbb\naaaa: cccc - dddd\nbbbb: humn / eeee\n\
+                       cccc: humn * eeee\ndddd: ffff * humn\neeee: 3\nffff: 6\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        let (lhs, rhs) = monkeys.equation::<i64>().unwrap();
+        assert_eq!(lhs.to_string(), "(humn * 3) - (6 * humn)");
+        assert_eq!(format!("{lhs:#}"), "humn * 3 - 6 * humn");
+        assert_eq!(format!("{rhs:#}"), "humn / 3");
+
+        let expr = Expr::Operation(
+            Op::Sub,
+            Box::new(Expr::Number(1)),
+            Box::new(Expr::Operation(
+                Op::Sub,
+                Box::new(Expr::Human),
+                Box::new(Expr::Number(2)),
+            )),
+        );
+        assert_eq!(format!("{expr:#}"), "1 - (humn - 2)");
+    }
+
+    #[test]
+    fn unsolvable() {
+        let monkeys = "root: aaaa + bbbb\naaaa: humn * humn\nbbbb: 4\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert!(monkeys.solve::<i64>().is_err());
+
+        let monkeys = "root: aaaa + humn\naaaa: humn - bbbb\nbbbb: 4\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert!(monkeys.solve::<i64>().is_err());
+
+        // 2 * humn = 7
+        let monkeys = "root: aaaa + bbbb\naaaa: cccc * humn\nbbbb: 7\ncccc: 2\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert!(monkeys.solve::<i64>().is_err());
+
+        // 3 * humn = 9
+        let monkeys = "root: aaaa + bbbb\naaaa: cccc * humn\nbbbb: 9\ncccc: 3\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(monkeys.solve::<i64>().unwrap(), 3);
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |input: &str| input.parse::<Monkeys>().err().unwrap().to_string();
+        assert_eq!(
+            error("root: aaaa + bbbb\naaaa: 1"),
+            "monkey root waits for unknown monkey bbbb"
+        );
+        assert_eq!(
+            error("root: aaaa + bbbb\naaaa: bbbb * cccc\nbbbb: cccc - aaaa\ncccc: 1"),
+            "monkeys wait for each other: aaaa -> bbbb -> aaaa"
+        );
+        assert_eq!(
+            error("root: root / root"),
+            "monkeys wait for each other: root -> root"
+        );
+        assert_eq!(error("root: 1\nroot: 2"), "monkey root appears twice");
+        assert_eq!(error("aaaa: 1"), "no root monkey");
+        assert_eq!(error("root: aaaa % bbbb"), "unknown operation \"%\"");
+        assert!("root: aaaa +".parse::<Monkeys>().is_err());
+        assert!("root 1".parse::<Monkeys>().is_err());
+
+        let monkeys = "root: aaaa / bbbb\naaaa: 1\nbbbb: humn - cccc\ncccc: 1\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(
+            monkeys.yell::<i64>().unwrap_err().to_string(),
+            "monkey root divides by zero"
+        );
+        assert!(monkeys.expr::<i64>("eeee").is_err());
+    }
+
+    #[test]
+    fn deep_chain() {
+        // each monkey adds one to the next, far deeper than the stack allows
+        // for recursion
+        let depth = 100_000;
+        let mut input = String::from("root: m0 + humn\nhumn: 5\none: 1\n");
+        for i in 0..depth {
+            writeln!(input, "m{i}: m{} + one", i + 1).unwrap();
         }
+        writeln!(input, "m{depth}: 0").unwrap();
+        let monkeys = input.parse::<Monkeys>().unwrap();
+        assert_eq!(monkeys.yell::<i64>().unwrap(), depth + 5);
+        let (lhs, rhs) = monkeys.equation::<i64>().unwrap();
+        assert_eq!((lhs, rhs), (Expr::Number(depth), Expr::Human));
+
+        // the same down to the human, whose formula may only nest so deep
+        let chain = |depth: usize| {
+            let mut input = String::from("root: m0 + ten\nten: 10\nhumn: 5\none: 1\n");
+            for i in 0..depth {
+                let next = if i + 1 == depth {
+                    HUMAN.to_string()
+                } else {
+                    format!("m{}", i + 1)
+                };
+                writeln!(input, "m{i}: {next} + one").unwrap();
+            }
+            input.parse::<Monkeys>().unwrap()
+        };
+        let monkeys = chain(MAX_DEPTH);
+        let (lhs, rhs) = monkeys.equation::<i64>().unwrap();
+        assert_eq!(
+            format!("{lhs:#}"),
+            format!("humn{}", " + 1".repeat(MAX_DEPTH))
+        );
+        assert_eq!(rhs, Expr::Number(10));
+        assert_eq!(
+            monkeys.solve::<i64>().unwrap(),
+            10 - isize::try_from(MAX_DEPTH).unwrap()
+        );
+
+        let monkeys = chain(200_000);
+        assert_eq!(
+            monkeys.equation::<i64>().unwrap_err().to_string(),
+            "the expression of monkey m198999 nests more than 1000 operations"
+        );
+        assert_eq!(monkeys.solve::<i64>().unwrap(), 10 - 200_000);
     }
-    while monkeys.cmp(&lhs, &rhs, answer) == std::cmp::Ordering::Equal {
-        answer -= 1;
+
+    #[test]
+    fn shared() {
+        // each monkey waits twice for the next, doubling the formula each time
+        let wide = |levels: usize| {
+            let mut input = String::from("root: m0 + ten\nten: 10\nhumn: 5\n");
+            for i in 0..levels {
+                let next = if i + 1 == levels {
+                    HUMAN.to_string()
+                } else {
+                    format!("m{}", i + 1)
+                };
+                writeln!(input, "m{i}: {next} + {next}").unwrap();
+            }
+            input.parse::<Monkeys>().unwrap()
+        };
+        let monkeys = wide(3);
+        let (lhs, _) = monkeys.equation::<i64>().unwrap();
+        assert_eq!(
+            lhs.to_string(),
+            "((humn + humn) + (humn + humn)) + ((humn + humn) + (humn + humn))"
+        );
+        assert_eq!(monkeys.yell::<i64>().unwrap(), 8 * 5 + 10);
+
+        let monkeys = wide(40);
+        assert_eq!(
+            monkeys.equation::<i64>().unwrap_err().to_string(),
+            "the expression of monkey m24 holds more than 100000 nodes"
+        );
     }
 
-    println!("Part 2: {}", answer + 1);
+    #[test]
+    fn backends() {
+        let monkeys = include_str!("../input/test.txt")
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(monkeys.yell::<i128>().unwrap(), 152);
+        assert_eq!(monkeys.solve::<i128>().unwrap(), 301);
+        assert_eq!(monkeys.yell::<BigRational>().unwrap().integer(), Some(152));
+        assert_eq!(monkeys.solve::<BigRational>().unwrap(), 301);
 
-    Ok(())
+        let monkeys = "root: aaaa * aaaa\naaaa: 9999999999\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(
+            monkeys.yell::<i64>().unwrap_err().to_string(),
+            "monkey root overflows"
+        );
+        assert_eq!(monkeys.yell::<i128>().unwrap(), 99_999_999_980_000_000_001);
+
+        let monkeys = "root: aaaa / bbbb\naaaa: 7\nbbbb: 2\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(
+            monkeys.yell::<i64>().unwrap_err().to_string(),
+            "monkey root divides inexactly"
+        );
+        assert_eq!(monkeys.yell::<i128>().unwrap(), 3);
+        assert_eq!(monkeys.yell::<BigRational>().unwrap().to_string(), "7/2");
+
+        // 7 / humn = 3 only has an answer with truncating division
+        let monkeys = "root: aaaa + bbbb\naaaa: cccc / humn\nbbbb: 3\ncccc: 7\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(
+            monkeys.solve::<i64>().unwrap_err().to_string(),
+            "undoing monkey aaaa: 7 / 3 divides inexactly"
+        );
+        assert_eq!(monkeys.solve::<i128>().unwrap(), 2);
+        assert_eq!(
+            monkeys.solve::<BigRational>().unwrap_err().to_string(),
+            "humn must yell 7/3, not an integer"
+        );
+
+        // 2 * humn = 7
+        let monkeys = "root: aaaa + bbbb\naaaa: cccc * humn\nbbbb: 7\ncccc: 2\nhumn: 1"
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(
+            monkeys.solve::<i64>().unwrap_err().to_string(),
+            "undoing monkey aaaa: 7 / 2 divides inexactly"
+        );
+        assert_eq!(
+            monkeys.solve::<i128>().unwrap_err().to_string(),
+            "humn yelling 3 does not balance root"
+        );
+        assert_eq!(
+            monkeys.solve::<BigRational>().unwrap_err().to_string(),
+            "humn must yell 7/2, not an integer"
+        );
+    }
 }
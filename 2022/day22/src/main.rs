@@ -1,6 +1,6 @@
-use std::{fmt, io};
+use std::{collections::HashMap, env, fmt, fmt::Write, fs, io};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Result};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Tile {
@@ -18,6 +18,8 @@ enum Direction {
 }
 
 impl Direction {
+    const ALL: [Self; 4] = [Self::Up, Self::Right, Self::Down, Self::Left];
+
     fn turn_left(self) -> Self {
         match self {
             Self::Up => Self::Left,
@@ -36,6 +38,15 @@ impl Direction {
         }
     }
 
+    fn opposite(self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+
     fn facing(self) -> usize {
         match self {
             Self::Up => 3,
@@ -57,11 +68,236 @@ impl fmt::Display for Direction {
     }
 }
 
+type Pos = (usize, usize);
+
+/// A direction in space.
+type Vector = [i32; 3];
+
+fn neg(v: Vector) -> Vector {
+    [-v[0], -v[1], -v[2]]
+}
+
+/// A face of the cube, as a square of the map and how it sits in space.
+#[derive(Debug, Clone, Copy)]
+struct Face {
+    /// The top left tile of the face on the map.
+    origin: Pos,
+    /// The direction the face points to, out of the cube.
+    normal: Vector,
+    /// The directions going right and down on the map.
+    right: Vector,
+    down: Vector,
+}
+
+impl Face {
+    /// Returns the direction in space of a direction on the map.
+    fn vector(&self, dir: Direction) -> Vector {
+        match dir {
+            Direction::Up => neg(self.down),
+            Direction::Down => self.down,
+            Direction::Left => neg(self.right),
+            Direction::Right => self.right,
+        }
+    }
+
+    /// Returns the `i`th tile along the edge of the face on the `dir` side,
+    /// going clockwise on the map.
+    fn edge_tile(&self, size: usize, dir: Direction, i: usize) -> Pos {
+        let (x, y) = self.origin;
+        let last = size - 1;
+        match dir {
+            Direction::Up => (x + i, y),
+            Direction::Right => (x + last, y + i),
+            Direction::Down => (x + last - i, y + last),
+            Direction::Left => (x, y + last - i),
+        }
+    }
+
+    /// Returns which side of the cube the face is on, the first face of the
+    /// map being on top.
+    fn side(&self) -> &'static str {
+        match self.normal {
+            [0, 0, -1] => "top",
+            [0, 0, 1] => "bottom",
+            [0, -1, 0] => "back",
+            [0, 1, 0] => "front",
+            [-1, 0, 0] => "left",
+            _ => "right",
+        }
+    }
+
+    /// Returns the direction going clockwise along the edge on the `dir`
+    /// side.
+    fn edge_vector(&self, dir: Direction) -> Vector {
+        self.vector(dir.turn_right())
+    }
+}
+
+/// Finds the six faces of the map and folds them into a cube, returning the
+/// size of the faces and the faces.
+fn fold(tiles: &[Vec<Tile>]) -> Result<(usize, Vec<Face>)> {
+    let area = tiles.iter().flatten().filter(|&&t| t != Tile::None).count();
+    ensure!(area % 6 == 0, "the map cannot fold into a cube");
+    let size = (area / 6).isqrt();
+    ensure!(
+        size > 0 && size * size * 6 == area,
+        "the map cannot fold into a cube"
+    );
+
+    let height = tiles.len().div_ceil(size);
+    let width = tiles[0].len().div_ceil(size);
+    let is_face = |fx: usize, fy: usize| tiles[fy * size][fx * size] != Tile::None;
+    let start = (0..width)
+        .find(|&fx| is_face(fx, 0))
+        .ok_or_else(|| anyhow!("the map has no first row"))?;
+
+    // unfold the net from the first face, rolling the cube over each edge
+    let mut faces = vec![Face {
+        origin: (start * size, 0),
+        normal: [0, 0, -1],
+        right: [1, 0, 0],
+        down: [0, 1, 0],
+    }];
+    let mut next = 0;
+    while let Some(&face) = faces.get(next) {
+        next += 1;
+        let (fx, fy) = (face.origin.0 / size, face.origin.1 / size);
+        let neighbors = [
+            (fy > 0).then(|| (fx, fy - 1, Direction::Up)),
+            (fy + 1 < height).then_some((fx, fy + 1, Direction::Down)),
+            (fx > 0).then(|| (fx - 1, fy, Direction::Left)),
+            (fx + 1 < width).then_some((fx + 1, fy, Direction::Right)),
+        ];
+        for (nx, ny, dir) in neighbors.into_iter().flatten() {
+            let origin = (nx * size, ny * size);
+            if !is_face(nx, ny) || faces.iter().any(|f| f.origin == origin) {
+                continue;
+            }
+            let normal = face.vector(dir);
+            let back = neg(face.normal);
+            let (right, down) = match dir {
+                Direction::Up => (face.right, face.normal),
+                Direction::Down => (face.right, back),
+                Direction::Left => (face.normal, face.down),
+                Direction::Right => (back, face.down),
+            };
+            faces.push(Face {
+                origin,
+                normal,
+                right,
+                down,
+            });
+        }
+    }
+    ensure!(faces.len() == 6, "the map has {} faces", faces.len());
+    for (i, face) in faces.iter().enumerate() {
+        if faces[..i].iter().any(|f| f.normal == face.normal) {
+            bail!("two faces of the map overlap when folded");
+        }
+    }
+    Ok((size, faces))
+}
+
+/// Where each step off the edge of the map leads.
+#[derive(Debug)]
+struct Wrap {
+    /// The tile and direction after stepping off the map from a tile in a
+    /// direction.
+    edges: HashMap<(Pos, Direction), (Pos, Direction)>,
+}
+
+impl Wrap {
+    /// Wraps each row and column of the map around on itself.
+    fn flat(tiles: &[Vec<Tile>]) -> Self {
+        let (height, width) = (tiles.len(), tiles[0].len());
+        let is_tile = |(x, y): Pos| tiles[y][x] != Tile::None;
+        // the next position in a direction, going around the whole map
+        let next = |(x, y): Pos, dir| match dir {
+            Direction::Up => (x, (y + height - 1) % height),
+            Direction::Down => (x, (y + 1) % height),
+            Direction::Left => ((x + width - 1) % width, y),
+            Direction::Right => ((x + 1) % width, y),
+        };
+
+        let mut edges = HashMap::new();
+        for y in 0..height {
+            for x in 0..width {
+                if !is_tile((x, y)) {
+                    continue;
+                }
+                for dir in Direction::ALL {
+                    let inside = match dir {
+                        Direction::Up => y > 0,
+                        Direction::Down => y + 1 < height,
+                        Direction::Left => x > 0,
+                        Direction::Right => x + 1 < width,
+                    };
+                    let mut to = next((x, y), dir);
+                    if inside && is_tile(to) {
+                        continue;
+                    }
+                    while !is_tile(to) {
+                        to = next(to, dir);
+                    }
+                    edges.insert(((x, y), dir), (to, dir));
+                }
+            }
+        }
+        Self { edges }
+    }
+
+    /// Folds the map into a cube, joining the edges of the faces that meet.
+    fn cube(tiles: &[Vec<Tile>]) -> Result<Self> {
+        let (size, faces) = fold(tiles)?;
+
+        // join the edges meeting in space
+        let mut edges = HashMap::new();
+        for face in &faces {
+            for dir in Direction::ALL {
+                let out = face.vector(dir);
+                let other = faces.iter().find(|f| f.normal == out).unwrap();
+                let other_dir = Direction::ALL
+                    .into_iter()
+                    .find(|&d| other.vector(d) == face.normal)
+                    .unwrap();
+                let reversed = other.edge_vector(other_dir) == neg(face.edge_vector(dir));
+                for i in 0..size {
+                    let j = if reversed { size - 1 - i } else { i };
+                    edges.insert(
+                        (face.edge_tile(size, dir, i), dir),
+                        (other.edge_tile(size, other_dir, j), other_dir.opposite()),
+                    );
+                }
+            }
+        }
+
+        Ok(Self { edges })
+    }
+
+    /// Returns the tile and direction after one step, wrapping around if it
+    /// leaves the map.
+    fn step(&self, pos: Pos, dir: Direction) -> (Pos, Direction) {
+        if let Some(&wrap) = self.edges.get(&(pos, dir)) {
+            return wrap;
+        }
+        let (x, y) = pos;
+        let pos = match dir {
+            Direction::Up => (x, y - 1),
+            Direction::Down => (x, y + 1),
+            Direction::Left => (x - 1, y),
+            Direction::Right => (x + 1, y),
+        };
+        (pos, dir)
+    }
+}
+
 #[derive(Clone)]
 struct Board {
     tiles: Vec<Vec<Tile>>,
     pos: (usize, usize),
     dir: Direction,
+    /// The last direction faced on every tile visited.
+    path: HashMap<Pos, Direction>,
 }
 
 impl Board {
@@ -83,195 +319,91 @@ impl Board {
             row.resize(max_col, Tile::None);
         }
         let first_open = tiles[0].iter().position(|t| *t == Tile::Open).unwrap();
+        let pos = (first_open, 0);
+        let dir = Direction::Right;
         Self {
             tiles,
-            pos: (first_open, 0),
-            dir: Direction::Right,
+            pos,
+            dir,
+            path: HashMap::from([(pos, dir)]),
         }
     }
 
-    fn action(&mut self, instruction: Instruction) {
+    fn action(&mut self, wrap: &Wrap, instruction: Instruction) {
         match instruction {
             Instruction::TurnLeft => self.dir = self.dir.turn_left(),
             Instruction::TurnRight => self.dir = self.dir.turn_right(),
             Instruction::Move(n) => {
                 for _ in 0..n {
-                    let new_pos = self.step();
+                    let (new_pos, new_dir) = self.step(wrap);
                     if self.tiles[new_pos.1][new_pos.0] == Tile::Open {
                         self.pos = new_pos;
+                        self.dir = new_dir;
+                        self.path.insert(self.pos, self.dir);
                     } else {
                         break;
                     }
                 }
             }
         }
+        self.path.insert(self.pos, self.dir);
     }
 
-    fn step(&self) -> (usize, usize) {
-        let mut next_pos = self.pos;
-        match self.dir {
-            Direction::Up => loop {
-                let new_y = if next_pos.1 == 0 {
-                    self.tiles.len() - 1
-                } else {
-                    next_pos.1 - 1
-                };
-                if self.tiles[new_y][next_pos.0] != Tile::None {
-                    next_pos.1 = new_y;
-                    break;
-                }
-                next_pos.1 = new_y;
-            },
-            Direction::Down => loop {
-                let new_y = if next_pos.1 == self.tiles.len() - 1 {
-                    0
-                } else {
-                    next_pos.1 + 1
-                };
-                if self.tiles[new_y][next_pos.0] != Tile::None {
-                    next_pos.1 = new_y;
-                    break;
-                }
-                next_pos.1 = new_y;
-            },
-            Direction::Left => loop {
-                let new_x = if next_pos.0 == 0 {
-                    self.tiles[0].len() - 1
-                } else {
-                    next_pos.0 - 1
-                };
-                if self.tiles[next_pos.1][new_x] != Tile::None {
-                    next_pos.0 = new_x;
-                    break;
-                }
-                next_pos.0 = new_x;
-            },
-            Direction::Right => loop {
-                let new_x = if next_pos.0 == self.tiles[0].len() - 1 {
-                    0
-                } else {
-                    next_pos.0 + 1
-                };
-                if self.tiles[next_pos.1][new_x] != Tile::None {
-                    next_pos.0 = new_x;
-                    break;
-                }
-                next_pos.0 = new_x;
-            },
-        }
-        next_pos
+    fn step(&self, wrap: &Wrap) -> (Pos, Direction) {
+        wrap.step(self.pos, self.dir)
     }
 
-    fn cube_action(&mut self, instruction: Instruction) {
-        match instruction {
-            Instruction::TurnLeft => self.dir = self.dir.turn_left(),
-            Instruction::TurnRight => self.dir = self.dir.turn_right(),
-            Instruction::Move(n) => {
-                for _ in 0..n {
-                    let (new_pos, new_dir) = self.cube_step();
-                    if self.tiles[new_pos.1][new_pos.0] == Tile::Open {
-                        self.pos = new_pos;
-                        self.dir = new_dir;
-                    } else {
-                        break;
-                    }
-                }
-            }
+    fn render_tile(&self, pos: Pos) -> char {
+        if let Some(dir) = self.path.get(&pos) {
+            return dir.to_string().chars().next().unwrap();
+        }
+        match self.tiles[pos.1][pos.0] {
+            Tile::None => ' ',
+            Tile::Open => '.',
+            Tile::Solid => '#',
         }
     }
 
-    fn cube_step(&self) -> ((usize, usize), Direction) {
-        let mut next_pos = self.pos;
-        let mut next_dir = self.dir;
-        match self.dir {
-            Direction::Up => {
-                if next_pos.1 == 0 && 50 <= next_pos.0 && next_pos.0 < 100 {
-                    next_pos = (0, next_pos.0 + 100);
-                    next_dir = Direction::Right;
-                } else if next_pos.1 == 0 && 100 <= next_pos.0 && next_pos.0 < 150 {
-                    next_pos = (next_pos.0 - 100, 199);
-                } else if next_pos.1 == 100 && next_pos.0 < 50 {
-                    next_pos = (50, next_pos.0 + 50);
-                    next_dir = Direction::Right;
-                } else {
-                    next_pos.1 -= 1;
-                };
-                assert!(next_pos.0 < 150);
-                assert!(next_pos.1 < 200);
-            }
-            Direction::Down => {
-                if next_pos.1 == 49 && 100 <= next_pos.0 && next_pos.0 < 150 {
-                    next_pos = (99, next_pos.0 - 50);
-                    next_dir = Direction::Left;
-                } else if next_pos.1 == 149 && 50 <= next_pos.0 && next_pos.0 < 100 {
-                    next_pos = (49, next_pos.0 + 100);
-                    next_dir = Direction::Left;
-                } else if next_pos.1 == 199 && next_pos.0 < 50 {
-                    next_pos = (next_pos.0 + 100, 0);
-                } else {
-                    next_pos.1 += 1;
-                };
-                assert!(next_pos.0 < 150);
-                assert!(next_pos.1 < 200);
-            }
-            Direction::Left => {
-                if next_pos.0 == 0 && 100 <= next_pos.1 && next_pos.1 < 150 {
-                    next_pos = (50, 149 - next_pos.1);
-                    next_dir = Direction::Right;
-                } else if next_pos.0 == 0 && 150 <= next_pos.1 && next_pos.1 < 200 {
-                    next_pos = (next_pos.1 - 100, 0);
-                    next_dir = Direction::Down;
-                } else if next_pos.0 == 50 && next_pos.1 < 50 {
-                    next_pos = (0, 149 - next_pos.1);
-                    next_dir = Direction::Right;
-                } else if next_pos.0 == 50 && 50 <= next_pos.1 && next_pos.1 < 100 {
-                    next_pos = (next_pos.1 - 50, 100);
-                    next_dir = Direction::Down;
-                } else {
-                    next_pos.0 -= 1;
-                };
-                assert!(next_pos.0 < 150);
-                assert!(next_pos.1 < 200);
-            }
-            Direction::Right => {
-                if next_pos.0 == 149 && next_pos.1 < 50 {
-                    next_pos = (99, 149 - next_pos.1);
-                    next_dir = Direction::Left;
-                } else if next_pos.0 == 99 && 50 <= next_pos.1 && next_pos.1 < 100 {
-                    next_pos = (next_pos.1 + 50, 49);
-                    next_dir = Direction::Up;
-                } else if next_pos.0 == 99 && 100 <= next_pos.1 && next_pos.1 < 150 {
-                    next_pos = (149, 149 - next_pos.1);
-                    next_dir = Direction::Left;
-                } else if next_pos.0 == 49 && 150 <= next_pos.1 && next_pos.1 < 200 {
-                    next_pos = (next_pos.1 - 100, 149);
-                    next_dir = Direction::Up;
-                } else {
-                    next_pos.0 += 1;
-                };
-                assert!(next_pos.0 < 150);
-                assert!(next_pos.1 < 200);
-            }
+    /// Draws the map with the path taken, like the puzzle does.
+    fn render(&self) -> String {
+        let mut s = String::new();
+        for (y, row) in self.tiles.iter().enumerate() {
+            let line = (0..row.len())
+                .map(|x| self.render_tile((x, y)))
+                .collect::<String>();
+            s.push_str(line.trim_end());
+            s.push('\n');
         }
-        (next_pos, next_dir)
+        s
     }
 
-    #[allow(dead_code)]
-    fn print(&self) {
-        for (y, row) in self.tiles.iter().enumerate() {
-            for (x, tile) in row.iter().enumerate() {
-                if (x, y) == self.pos {
-                    print!("{}", self.dir);
-                } else {
-                    match tile {
-                        Tile::None => print!(" "),
-                        Tile::Open => print!("."),
-                        Tile::Solid => print!("#"),
-                    }
-                }
+    /// Draws each face of the cube with the path taken on it, in the order
+    /// they appear on the map.
+    fn render_faces(&self) -> Result<String> {
+        let (size, mut faces) = fold(&self.tiles)?;
+        faces.sort_by_key(|face| (face.origin.1, face.origin.0));
+        let mut s = String::new();
+        for (i, face) in faces.iter().enumerate() {
+            let (x, y) = face.origin;
+            writeln!(
+                s,
+                "== Face {}: {}, rows {}-{}, columns {}-{} ==",
+                i + 1,
+                face.side(),
+                y + 1,
+                y + size,
+                x + 1,
+                x + size
+            )?;
+            for y in y..y + size {
+                let line = (x..x + size)
+                    .map(|x| self.render_tile((x, y)))
+                    .collect::<String>();
+                writeln!(s, "{line}")?;
             }
-            println!();
+            s.push('\n');
         }
+        Ok(s)
     }
 }
 
@@ -318,24 +450,42 @@ fn main() -> Result<()> {
     let (board, instructions) = (&lines[..empty_line], &lines[empty_line + 1..]);
     let mut board = Board::new(board);
     let mut cube_board = board.clone();
+    let flat = Wrap::flat(&board.tiles);
     let instructions = parse_instructions(instructions[0]);
 
     for &instr in &instructions {
-        board.action(instr);
+        board.action(&flat, instr);
     }
     println!(
         "Part 1: {}",
         (board.pos.1 + 1) * 1000 + (board.pos.0 + 1) * 4 + board.dir.facing()
     );
 
+    // only part 2 needs the map to fold into a cube
+    let cube = Wrap::cube(&cube_board.tiles)?;
     for &instr in &instructions {
-        cube_board.cube_action(instr);
+        cube_board.action(&cube, instr);
     }
     println!(
         "Part 2: {}",
         (cube_board.pos.1 + 1) * 1000 + (cube_board.pos.0 + 1) * 4 + cube_board.dir.facing()
     );
 
+    // Optional flag: --trace <file>, saving the paths of both parts
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if let Some(i) = args.iter().position(|arg| arg == "--trace") {
+        let file = args
+            .get(i + 1)
+            .ok_or_else(|| anyhow!("missing trace file"))?;
+        let trace = format!(
+            "== Part 1 ==\n{}\n== Part 2 ==\n{}\n{}",
+            board.render(),
+            cube_board.render(),
+            cube_board.render_faces()?
+        );
+        fs::write(file, trace)?;
+    }
+
     Ok(())
 }
 
@@ -345,72 +495,201 @@ mod tests {
 
     #[test]
     fn cube_step() {
+        // the layout of the real input
+        let mut tiles = vec![vec![Tile::None; 150]; 200];
+        for (fx, fy) in [(1, 0), (2, 0), (1, 1), (0, 2), (1, 2), (0, 3)] {
+            for row in &mut tiles[fy * 50..(fy + 1) * 50] {
+                row[fx * 50..(fx + 1) * 50].fill(Tile::Open);
+            }
+        }
+        let cube = Wrap::cube(&tiles).unwrap();
         let mut board = Board {
             pos: (0, 0),
             dir: Direction::Up,
-            tiles: vec![vec![Tile::Solid; 200]; 150],
+            tiles,
+            path: HashMap::new(),
         };
 
         board.pos = (50, 0);
         board.dir = Direction::Up;
-        assert_eq!(board.cube_step(), ((0, 150), Direction::Right));
+        assert_eq!(board.step(&cube), ((0, 150), Direction::Right));
         board.pos = (99, 0);
         board.dir = Direction::Up;
-        assert_eq!(board.cube_step(), ((0, 199), Direction::Right));
+        assert_eq!(board.step(&cube), ((0, 199), Direction::Right));
 
         board.pos = (100, 0);
         board.dir = Direction::Up;
-        assert_eq!(board.cube_step(), ((0, 199), Direction::Up));
+        assert_eq!(board.step(&cube), ((0, 199), Direction::Up));
         board.pos = (149, 0);
         board.dir = Direction::Up;
-        assert_eq!(board.cube_step(), ((49, 199), Direction::Up));
+        assert_eq!(board.step(&cube), ((49, 199), Direction::Up));
 
         board.pos = (0, 100);
         board.dir = Direction::Up;
-        assert_eq!(board.cube_step(), ((50, 50), Direction::Right));
+        assert_eq!(board.step(&cube), ((50, 50), Direction::Right));
 
         board.pos = (0, 199);
         board.dir = Direction::Down;
-        assert_eq!(board.cube_step(), ((100, 0), Direction::Down));
+        assert_eq!(board.step(&cube), ((100, 0), Direction::Down));
 
         board.pos = (50, 149);
         board.dir = Direction::Down;
-        assert_eq!(board.cube_step(), ((49, 150), Direction::Left));
+        assert_eq!(board.step(&cube), ((49, 150), Direction::Left));
 
         board.pos = (100, 49);
         board.dir = Direction::Down;
-        assert_eq!(board.cube_step(), ((99, 50), Direction::Left));
+        assert_eq!(board.step(&cube), ((99, 50), Direction::Left));
 
         board.pos = (50, 0);
         board.dir = Direction::Left;
-        assert_eq!(board.cube_step(), ((0, 149), Direction::Right));
+        assert_eq!(board.step(&cube), ((0, 149), Direction::Right));
 
         board.pos = (50, 50);
         board.dir = Direction::Left;
-        assert_eq!(board.cube_step(), ((0, 100), Direction::Down));
+        assert_eq!(board.step(&cube), ((0, 100), Direction::Down));
 
         board.pos = (0, 100);
         board.dir = Direction::Left;
-        assert_eq!(board.cube_step(), ((50, 49), Direction::Right));
+        assert_eq!(board.step(&cube), ((50, 49), Direction::Right));
 
         board.pos = (0, 150);
         board.dir = Direction::Left;
-        assert_eq!(board.cube_step(), ((50, 0), Direction::Down));
+        assert_eq!(board.step(&cube), ((50, 0), Direction::Down));
 
         board.pos = (149, 0);
         board.dir = Direction::Right;
-        assert_eq!(board.cube_step(), ((99, 149), Direction::Left));
+        assert_eq!(board.step(&cube), ((99, 149), Direction::Left));
 
         board.pos = (99, 50);
         board.dir = Direction::Right;
-        assert_eq!(board.cube_step(), ((100, 49), Direction::Up));
+        assert_eq!(board.step(&cube), ((100, 49), Direction::Up));
 
         board.pos = (99, 100);
         board.dir = Direction::Right;
-        assert_eq!(board.cube_step(), ((149, 49), Direction::Left));
+        assert_eq!(board.step(&cube), ((149, 49), Direction::Left));
 
         board.pos = (49, 150);
         board.dir = Direction::Right;
-        assert_eq!(board.cube_step(), ((50, 149), Direction::Up));
+        assert_eq!(board.step(&cube), ((50, 149), Direction::Up));
+    }
+
+    #[test]
+    fn example() {
+        let input = include_str!("../input/test.txt");
+        let lines = input.lines().collect::<Vec<_>>();
+        let mut board = Board::new(&lines[..12]);
+        let flat = Wrap::flat(&board.tiles);
+        for instr in parse_instructions(lines[13]) {
+            board.action(&flat, instr);
+        }
+        assert_eq!((board.pos, board.dir), ((7, 5), Direction::Right));
+
+        let mut board = Board::new(&lines[..12]);
+        let cube = Wrap::cube(&board.tiles).unwrap();
+        for instr in parse_instructions(lines[13]) {
+            board.action(&cube, instr);
+        }
+        assert_eq!((board.pos, board.dir), ((6, 4), Direction::Up));
+
+        // going straight from any edge comes back after four faces
+        let board = Board::new(&lines[..12]);
+        for &start in cube.edges.keys() {
+            let (mut pos, mut dir) = start;
+            for _ in 0..16 {
+                (pos, dir) = cube.step(pos, dir);
+                assert_ne!(board.tiles[pos.1][pos.0], Tile::None);
+            }
+            assert_eq!((pos, dir), start);
+        }
+        assert_eq!(cube.edges.len(), 6 * 4 * 4);
+
+        assert!(Wrap::cube(&Board::new(&["....", "...."]).tiles).is_err());
+    }
+
+    #[test]
+    fn flat_wrap() {
+        let board = Board::new(&["  ..", ". ..", " ..."]);
+        let flat = Wrap::flat(&board.tiles);
+        assert_eq!(
+            flat.step((3, 0), Direction::Right),
+            ((2, 0), Direction::Right)
+        );
+        assert_eq!(
+            flat.step((2, 0), Direction::Left),
+            ((3, 0), Direction::Left)
+        );
+        // across the gap in the middle of a row
+        assert_eq!(
+            flat.step((0, 1), Direction::Right),
+            ((2, 1), Direction::Right)
+        );
+        assert_eq!(
+            flat.step((2, 1), Direction::Left),
+            ((0, 1), Direction::Left)
+        );
+        assert_eq!(
+            flat.step((1, 2), Direction::Down),
+            ((1, 2), Direction::Down)
+        );
+        assert_eq!(flat.step((3, 0), Direction::Up), ((3, 2), Direction::Up));
+        assert_eq!(
+            flat.step((2, 1), Direction::Down),
+            ((2, 2), Direction::Down)
+        );
+    }
+
+    #[test]
+    fn trace() {
+        let input = include_str!("../input/test.txt");
+        let lines = input.lines().collect::<Vec<_>>();
+        let instructions = parse_instructions(lines[13]);
+
+        let mut board = Board::new(&lines[..12]);
+        let flat = Wrap::flat(&board.tiles);
+        for &instr in &instructions {
+            board.action(&flat, instr);
+        }
+        assert_eq!(
+            board.render(),
+            "        >>v#
+        .#v.
+        #.v.
+        ..v.
+...#...v..v#
+>>>v...>#.>>
+..#v...#....
+...>>>>v..#.
+        ...#....
+        .....#..
+        .#......
+        ......#.
+"
+        );
+
+        let mut board = Board::new(&lines[..12]);
+        let cube = Wrap::cube(&board.tiles).unwrap();
+        for &instr in &instructions {
+            board.action(&cube, instr);
+        }
+        assert_eq!(
+            board.render(),
+            "        >>v#
+        .#v.
+        #.v.
+        ..v.
+...#..^...v#
+.>>>>>^.#.>>
+.^#....#....
+.^........#.
+        ...#..v.
+        .....#v.
+        .#v<<<<.
+        ..v...#.
+"
+        );
+        let faces = board.render_faces().unwrap();
+        assert!(faces.starts_with("== Face 1: top, rows 1-4, columns 9-12 ==\n>>v#\n"));
+        assert!(faces
+            .contains("== Face 6: right, rows 9-12, columns 13-16 ==\n..v.\n.#v.\n<<<.\n..#.\n"));
     }
 }
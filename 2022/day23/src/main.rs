@@ -1,171 +1,115 @@
-use std::io;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Tile {
-    Open,
-    Elf,
-}
+/// A position as (y, x), growing down and right.
+type Pos = (isize, isize);
 
 #[derive(Clone)]
 struct Board {
-    tiles: Vec<Vec<Tile>>,
+    elves: HashSet<Pos>,
+    /// The number of rounds done so far.
+    rounds: usize,
 }
 
 impl Board {
-    fn new(rows: &[&str]) -> Self {
-        const MARGIN: usize = 200;
-        let mut tiles = Vec::new();
-        let mid = rows
-            .iter()
-            .map(|row| {
-                let center = row
-                    .chars()
-                    .map(|c| match c {
-                        '.' => Tile::Open,
-                        '#' => Tile::Elf,
-                        _ => panic!("invalid tile"),
-                    })
-                    .collect::<Vec<_>>();
-                let mut full = Vec::new();
-                full.extend((0..MARGIN).map(|_| Tile::Open));
-                full.extend(center);
-                full.extend((0..MARGIN).map(|_| Tile::Open));
-                full
-            })
-            .collect::<Vec<_>>();
-        let width = mid[0].len();
-        tiles.extend((0..MARGIN).map(|_| vec![Tile::Open; width]));
-        tiles.extend(mid);
-        tiles.extend((0..MARGIN).map(|_| vec![Tile::Open; width]));
-        Self { tiles }
-    }
-
-    fn propose(&self, round: usize) -> Option<Vec<((usize, usize), (usize, usize))>> {
-        let mut proposals = Vec::new();
-        let mut moving = 0;
-        for (y, row) in self.tiles.iter().enumerate() {
-            for (x, tile) in row.iter().enumerate() {
-                if *tile == Tile::Elf {
-                    if let Some(pos) = self.elf_move(round, y, x) {
-                        proposals.push((pos, (y, x)));
-                        moving += 1;
-                    } else {
-                        proposals.push(((y, x), (y, x)));
+    fn new(rows: &[&str]) -> Result<Self> {
+        let mut elves = HashSet::new();
+        for (y, row) in (0..).zip(rows) {
+            for (x, c) in (0..).zip(row.chars()) {
+                match c {
+                    '.' => {}
+                    '#' => {
+                        elves.insert((y, x));
                     }
+                    _ => bail!("invalid tile {c:?}"),
                 }
             }
         }
-        if moving > 0 {
-            Some(proposals)
-        } else {
-            None
-        }
+        Ok(Self { elves, rounds: 0 })
     }
 
-    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
-    fn elf_move(&self, round: usize, y: usize, x: usize) -> Option<(usize, usize)> {
+    /// Returns where the elf proposes to move to, if anywhere.
+    fn propose(&self, (y, x): Pos) -> Option<Pos> {
         const DIRECTIONS: [[(isize, isize); 3]; 4] = [
             [(-1, -1), (-1, 0), (-1, 1)],
             [(1, -1), (1, 0), (1, 1)],
             [(-1, -1), (0, -1), (1, -1)],
             [(-1, 1), (0, 1), (1, 1)],
         ];
-        let mut is_neighborhood_empty = true;
-        for i in -1..=1 {
-            for j in -1..=1 {
-                if i == 0 && j == 0 {
-                    continue;
-                }
-                let (y, x) = (y as isize + i, x as isize + j);
-                assert!(y >= 0 && x >= 0);
-                let (y, x) = (y as usize, x as usize);
-                assert!(y < self.tiles.len() && x < self.tiles[0].len());
-                if self.tiles[y][x] != Tile::Open {
-                    is_neighborhood_empty = false;
-                }
-            }
-        }
-        if is_neighborhood_empty {
+        let is_elf = |(dy, dx): (isize, isize)| self.elves.contains(&(y + dy, x + dx));
+
+        let alone = (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dy, dx)))
+            .filter(|&offset| offset != (0, 0))
+            .all(|offset| !is_elf(offset));
+        if alone {
             return None;
         }
-        for i in 0..4 {
-            let offsets = DIRECTIONS[(i + round) % 4];
-            if offsets.iter().all(|(dy, dx)| {
-                let (ny, nx) = (y as isize + dy, x as isize + dx);
-                if ny < 0 || ny >= self.tiles.len() as isize {
-                    return false;
-                }
-                if nx < 0 || nx >= self.tiles[ny as usize].len() as isize {
-                    return false;
-                }
-                match self.tiles[ny as usize][nx as usize] {
-                    Tile::Open => true,
-                    Tile::Elf => false,
-                }
-            }) {
-                return Some((
-                    (y as isize + offsets[1].0) as usize,
-                    (x as isize + offsets[1].1) as usize,
-                ));
-            }
-        }
-        None
+        (0..4)
+            .map(|i| DIRECTIONS[(i + self.rounds) % 4])
+            .find(|offsets| offsets.iter().all(|&offset| !is_elf(offset)))
+            .map(|offsets| (y + offsets[1].0, x + offsets[1].1))
     }
 
-    fn relocate(&mut self, proposals: &[(usize, usize)]) {
-        for y in 0..self.tiles.len() {
-            for x in 0..self.tiles[y].len() {
-                if proposals.contains(&(y, x)) {
-                    self.tiles[y][x] = Tile::Elf;
-                } else {
-                    self.tiles[y][x] = Tile::Open;
-                }
+    /// Plays a round, returning whether any elf moved.
+    fn round(&mut self) -> bool {
+        let proposals = self
+            .elves
+            .iter()
+            .filter_map(|&elf| Some((elf, self.propose(elf)?)))
+            .collect::<Vec<_>>();
+        let mut counts = HashMap::new();
+        for &(_, to) in &proposals {
+            *counts.entry(to).or_insert(0) += 1;
+        }
+
+        let mut moved = false;
+        for (from, to) in proposals {
+            if counts[&to] == 1 {
+                self.elves.remove(&from);
+                self.elves.insert(to);
+                moved = true;
             }
         }
+        self.rounds += 1;
+        moved
     }
 
-    fn encompassing_rectangle(&self) -> ((usize, usize), (usize, usize)) {
-        let mut min_y = self.tiles.len();
-        let mut max_y = 0;
-        let mut min_x = self.tiles[0].len();
-        let mut max_x = 0;
-        for y in 0..self.tiles.len() {
-            for x in 0..self.tiles[y].len() {
-                if self.tiles[y][x] == Tile::Elf {
-                    min_y = min_y.min(y);
-                    max_y = max_y.max(y);
-                    min_x = min_x.min(x);
-                    max_x = max_x.max(x);
-                }
-            }
+    fn encompassing_rectangle(&self) -> (Pos, Pos) {
+        let mut min = (isize::MAX, isize::MAX);
+        let mut max = (isize::MIN, isize::MIN);
+        for &(y, x) in &self.elves {
+            min = (min.0.min(y), min.1.min(x));
+            max = (max.0.max(y), max.1.max(x));
         }
-        ((min_y, min_x), (max_y, max_x))
+        (min, max)
     }
 
-    fn count_elves_in_rectangle(
-        &self,
-        ((min_y, min_x), (max_y, max_x)): ((usize, usize), (usize, usize)),
-    ) -> usize {
-        let mut count = 0;
-        for y in min_y..=max_y {
-            for x in min_x..=max_x {
-                if self.tiles[y][x] == Tile::Elf {
-                    count += 1;
-                }
-            }
+    /// Returns the number of empty tiles in the smallest rectangle holding
+    /// all the elves.
+    #[allow(clippy::cast_sign_loss)]
+    fn empty_ground(&self) -> usize {
+        if self.elves.is_empty() {
+            return 0;
         }
-        count
+        let ((min_y, min_x), (max_y, max_x)) = self.encompassing_rectangle();
+        let area = (max_y - min_y + 1) * (max_x - min_x + 1);
+        area as usize - self.elves.len()
     }
 
     #[allow(dead_code)]
     fn print(&self) {
-        for row in &self.tiles {
-            for tile in row {
-                let c = match tile {
-                    Tile::Open => '.',
-                    Tile::Elf => '#',
+        let ((min_y, min_x), (max_y, max_x)) = self.encompassing_rectangle();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let c = if self.elves.contains(&(y, x)) {
+                    '#'
+                } else {
+                    '.'
                 };
                 print!("{c}");
             }
@@ -177,58 +121,50 @@ impl Board {
 fn main() -> Result<()> {
     let input = io::read_to_string(io::stdin())?;
     let lines = input.lines().collect::<Vec<_>>();
-    let mut board = Board::new(&lines);
-    for i in 0..10 {
-        let mut proposals = board.propose(i).unwrap();
-        proposals.sort_unstable();
-        let mut next_pos = Vec::new();
-        let mut j = 0;
-        while j < proposals.len() {
-            if j < proposals.len() - 1 && proposals[j].0 == proposals[j + 1].0 {
-                while j < proposals.len() - 1 && proposals[j].0 == proposals[j + 1].0 {
-                    next_pos.push(proposals[j].1);
-                    j += 1;
-                }
-                next_pos.push(proposals[j].1);
-            } else {
-                next_pos.push(proposals[j].0);
-            }
-            j += 1;
-        }
-        board.relocate(&next_pos);
+    let mut board = Board::new(&lines)?;
+    for _ in 0..10 {
+        board.round();
     }
-    let ((min_y, min_x), (max_y, max_x)) = board.encompassing_rectangle();
-    let count = board.count_elves_in_rectangle(((min_y, min_x), (max_y, max_x)));
-    println!(
-        "Part 1: {}",
-        (max_x - min_x + 1) * (max_y - min_y + 1) - count
-    );
-
-    let mut board = Board::new(&lines);
-    let mut i = 0;
-    loop {
-        let Some(mut proposals) = board.propose(i) else {
-            break;
-        };
-        proposals.sort_unstable();
-        let mut next_pos = Vec::new();
-        let mut j = 0;
-        while j < proposals.len() {
-            if j < proposals.len() - 1 && proposals[j].0 == proposals[j + 1].0 {
-                while j < proposals.len() - 1 && proposals[j].0 == proposals[j + 1].0 {
-                    next_pos.push(proposals[j].1);
-                    j += 1;
-                }
-                next_pos.push(proposals[j].1);
-            } else {
-                next_pos.push(proposals[j].0);
-            }
-            j += 1;
+    println!("Part 1: {}", board.empty_ground());
+
+    let mut board = Board::new(&lines)?;
+    while board.round() {}
+    println!("Part 2: {}", board.rounds);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example() {
+        let lines = include_str!("../input/test.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let mut board = Board::new(&lines).unwrap();
+        for _ in 0..10 {
+            assert!(board.round());
         }
-        board.relocate(&next_pos);
-        i += 1;
+        assert_eq!(board.empty_ground(), 110);
+        while board.round() {}
+        assert_eq!(board.rounds, 20);
     }
-    println!("Part 2: {}", i + 1);
 
-    Ok(())
+    #[test]
+    fn small() {
+        let mut board =
+            Board::new(&[".....", "..##.", "..#..", ".....", "..##.", "....."]).unwrap();
+        while board.round() {}
+        assert_eq!(board.rounds, 4);
+        let expected = Board::new(&["..#..", "....#", "#....", "....#", ".....", "..#.."]).unwrap();
+        let (min, _) = board.encompassing_rectangle();
+        let shifted = board
+            .elves
+            .iter()
+            .map(|&(y, x)| (y - min.0, x - min.1))
+            .collect::<HashSet<_>>();
+        assert_eq!(shifted, expected.elves);
+    }
 }